@@ -3,6 +3,7 @@ use std::process::Command;
 pub struct Linker {
 	input_files: Vec<String>,
 	output_file: String,
+	target: Option<String>,
 	dynamic: bool,
 	demangle: bool,
 }
@@ -11,6 +12,9 @@ impl Linker {
 		let mut command = Command::new("clang");
 		command.arg("-g");
 		command.arg("-o").arg(&self.output_file);
+		if let Some(target) = &self.target {
+			command.arg(format!("--target={}", target));
+		}
 		for file in &self.input_files {
 			command.arg(file);
 		}
@@ -25,6 +29,7 @@ impl Linker {
 		Linker {
 			input_files: Vec::new(),
 			output_file: "testing".to_string(),
+			target: None,
 			dynamic: true,
 			demangle: true,
 		}
@@ -35,6 +40,11 @@ impl Linker {
 		self
 	}
 
+	pub fn target<'a>(&'a mut self, triple: &str) -> &'a Self {
+		self.target = Some(triple.to_string());
+		self
+	}
+
 	pub fn output<'a>(&'a mut self, name: &str) -> &'a Self {
 		self.output_file = name.to_string();
 		self
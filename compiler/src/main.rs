@@ -2,14 +2,23 @@
 mod config;
 mod linker;
 
+use serde_json::json;
 use std::collections::HashMap;
 use std::env;
-use stream::compile::compile;
+use std::io::{self, IsTerminal};
+use std::ops::Range;
+use stream::compile::{compile, create_target_machine, run_jit};
+use stream::errors::{get_pos, CompError};
+use stream::interpret::interpret;
 use stream::runner::*;
-use stream::settings::Settings;
+use stream::settings::{Emit, OptLevel, Settings};
 
 fn main() {
-	let entry_name = env::args().nth(1).expect("Expected file argument");
+	// `stream run file.srm` runs the program in process instead of producing a binary
+	let jit = env::args().nth(1).as_deref() == Some("run");
+	let entry_name = env::args()
+		.nth(if jit { 2 } else { 1 })
+		.expect("Expected file argument");
 	let name = resolve_path(&entry_name);
 	let args = env::args().collect::<Vec<_>>();
 	let name = if let Some(name) = name {
@@ -18,38 +27,265 @@ fn main() {
 		eprintln!("The file '{}' does not exist", entry_name);
 		return;
 	};
+	let emit = match get_flag_value(&args, "--emit") {
+		Some(kinds) => {
+			let emit = kinds
+				.split(',')
+				.map(Emit::from_flag)
+				.collect::<Option<Vec<_>>>();
+			if let Some(emit) = emit {
+				emit
+			} else {
+				eprintln!(
+					"Invalid value for --emit '{}', expected a list of obj, llvm-ir, llvm-bc or asm",
+					kinds
+				);
+				return;
+			}
+		}
+		None => vec![Emit::Object],
+	};
+	let error_format = match get_flag_value(&args, "--error-format").as_deref() {
+		None | Some("human") => ErrorFormat::Human,
+		Some("json") => ErrorFormat::Json,
+		Some(x) => {
+			eprintln!(
+				"Invalid value for --error-format '{}', expected human or json",
+				x
+			);
+			return;
+		}
+	};
 	let settings = Settings {
 		print_llvm: args.contains(&"-p".to_string()),
-		skip_optimizations: !args.contains(&"-s".to_string()),
+		opt_level: get_opt_level(&args),
 		call_linker: true,
 		input_name: name.clone(),
 		object_name: name.replace(".srm", ".o"),
+		target_triple: get_flag_value(&args, "--target"),
+		cpu: get_flag_value(&args, "--cpu"),
+		cpu_features: get_flag_value(&args, "--features"),
+		emit,
 	};
+	if let Err(msg) = create_target_machine(&settings) {
+		eprintln!("{}", msg);
+		return;
+	}
 	let mut files = parse_files(settings.clone(), HashMap::new());
 	transform_files(&name, &mut files);
-	let mut errors = false;
-	for prog in &files {
-		if prog.1.errors.is_empty() {
-			let comp_res = compile(prog.1.program.as_ref().unwrap(), prog.1.settings.clone());
+	if args.contains(&"-i".to_string()) {
+		run_interpreter(&name, &files, error_format);
+	} else if jit {
+		run_in_process(&name, &files, error_format);
+	}
+	let errors = report_errors(&files, error_format);
+	for prog in files.values() {
+		if !prog.has_errors() {
+			let comp_res = compile(prog.program.as_ref().unwrap(), prog.settings.clone());
 			if let Err(msg) = comp_res {
 				eprintln!("Internal compiler error:\n {}", msg);
 				return;
 			}
-		} else {
-			errors = true;
-			prog.1
-				.errors
-				.iter()
-				.for_each(|e| println!("{}", e.get_msg(&prog.1.line_numbers)));
 		}
 	}
-	if settings.call_linker && !errors {
+	if settings.call_linker && settings.emit.contains(&Emit::Object) && !errors {
 		let mut linker = linker::Linker::new();
 		for file in files {
 			linker.input(&file.1.settings.object_name);
 		}
 
+		if let Some(triple) = &settings.target_triple {
+			linker.target(triple);
+		}
 		linker.output("testing");
 		linker.link().unwrap_or_else(|x| panic!("{}", x));
 	};
 }
+
+/// Reads the optimization level from `-O0` through `-O3` or `-Os`
+/// `-s` is kept as a shorthand for `-O0`
+fn get_opt_level(args: &[String]) -> OptLevel {
+	if let Some(level) = args
+		.iter()
+		.find_map(|x| x.strip_prefix("-O").and_then(OptLevel::from_flag))
+	{
+		level
+	} else if args.contains(&"-s".to_string()) {
+		OptLevel::O0
+	} else {
+		OptLevel::default()
+	}
+}
+
+/// Finds the value of a flag passed as either `--flag value` or `--flag=value`
+fn get_flag_value(args: &[String], flag: &str) -> Option<String> {
+	let prefix = format!("{}=", flag);
+	args.iter().enumerate().find_map(|(i, arg)| {
+		if arg == flag {
+			args.get(i + 1).cloned()
+		} else {
+			arg.strip_prefix(&prefix).map(String::from)
+		}
+	})
+}
+
+#[derive(Clone, Copy)]
+enum ErrorFormat {
+	Human,
+	/// One JSON object per line for editors and other tools
+	Json,
+}
+
+/// Prints the errors and warnings for every file, returning true if there were any errors
+fn report_errors(files: &HashMap<String, ImportMap>, format: ErrorFormat) -> bool {
+	let colour = io::stdout().is_terminal();
+	let mut errors = false;
+	for prog in files.values() {
+		errors |= prog.has_errors();
+		for e in &prog.errors {
+			match format {
+				ErrorFormat::Human => println!("{}", e.render(prog, files, colour)),
+				ErrorFormat::Json => println!("{}", error_json(e, prog, files)),
+			}
+		}
+	}
+	errors
+}
+
+fn error_json(
+	e: &CompError,
+	file: &ImportMap,
+	files: &HashMap<String, ImportMap>,
+) -> serde_json::Value {
+	let related = e
+		.get_labels()
+		.into_iter()
+		.map(|label| {
+			let lines = if label.file == file.file {
+				Some(&file.line_numbers)
+			} else {
+				files.get(&label.file).map(|x| &x.line_numbers)
+			};
+			let mut value = location_json(&label.file, &label.loc, lines.map(Vec::as_slice));
+			value["message"] = json!(label.msg);
+			value
+		})
+		.collect::<Vec<_>>();
+	let mut value = location_json(&file.file, &e.get_loc(), Some(&file.line_numbers));
+	value["code"] = json!(e.get_code());
+	value["message"] = json!(e.get_description());
+	value["severity"] = json!(if e.is_warning() { "warning" } else { "error" });
+	value["related"] = json!(related);
+	value
+}
+
+/// Lines and columns start from 1, they are left out if the file's text isn't known
+fn location_json(file: &str, loc: &Range<usize>, lines: Option<&[i32]>) -> serde_json::Value {
+	let mut value = json!({
+		"file": file,
+		"span": {"start": loc.start, "end": loc.end},
+	});
+	if let Some(lines) = lines {
+		for (key, pos) in [("start", loc.start), ("end", loc.end)] {
+			let pos = get_pos(pos as i32, lines);
+			value[key] = json!({"line": pos.line + 1, "column": pos.column + 1});
+		}
+	}
+	value
+}
+
+/// Runs the program with the tree walking interpreter instead of compiling it
+/// Exits with the value returned from main
+fn run_interpreter(name: &str, files: &HashMap<String, ImportMap>, format: ErrorFormat) -> ! {
+	if report_errors(files, format) {
+		std::process::exit(1);
+	}
+	match interpret(name, files) {
+		Ok(code) => std::process::exit(code),
+		Err(msg) => {
+			eprintln!("Runtime error:\n {}", msg);
+			std::process::exit(1);
+		}
+	}
+}
+
+/// Compiles the program and runs it through the JIT without writing any files
+/// Exits with the value returned from main
+fn run_in_process(name: &str, files: &HashMap<String, ImportMap>, format: ErrorFormat) -> ! {
+	if report_errors(files, format) {
+		std::process::exit(1);
+	}
+	match run_jit(name, files) {
+		Ok(code) => std::process::exit(code),
+		Err(msg) => {
+			eprintln!("Internal compiler error:\n {}", msg);
+			std::process::exit(1);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn file(src: &str) -> ImportMap {
+		let settings = Settings {
+			print_llvm: false,
+			opt_level: OptLevel::O0,
+			call_linker: false,
+			input_name: "test.srm".to_string(),
+			object_name: String::new(),
+			target_triple: None,
+			cpu: None,
+			cpu_features: None,
+			emit: Vec::new(),
+		};
+		ImportMap::new(settings).text(src.to_string())
+	}
+
+	#[test]
+	fn error_as_json() {
+		let file = file("let a = 1\nlet b = c");
+		let e = CompError::CannotFindVariable("c".to_string(), 18..19);
+		assert_eq!(
+			error_json(&e, &file, &HashMap::new()),
+			json!({
+				"file": "test.srm",
+				"span": {"start": 18, "end": 19},
+				"start": {"line": 2, "column": 9},
+				"end": {"line": 2, "column": 10},
+				"code": 6,
+				"message": "Cannot find variable 'c'",
+				"severity": "error",
+				"related": [],
+			})
+		);
+	}
+
+	#[test]
+	fn labels_as_related_json() {
+		let file = file("const a = 1\na = 2");
+		let declared_at = Some(("test.srm".to_string(), 6..7));
+		let e = CompError::ConstReassign("a".to_string(), declared_at, 12..17);
+		assert_eq!(
+			error_json(&e, &file, &HashMap::new())["related"],
+			json!([{
+				"file": "test.srm",
+				"span": {"start": 6, "end": 7},
+				"start": {"line": 1, "column": 7},
+				"end": {"line": 1, "column": 8},
+				"message": "'a' is declared as a constant here",
+			}])
+		);
+	}
+
+	#[test]
+	fn warning_severity_in_json() {
+		let file = file("match 1 { _ => 1, 2 => 2 }");
+		let e = CompError::UnreachablePattern(18..19);
+		assert_eq!(
+			error_json(&e, &file, &HashMap::new())["severity"],
+			"warning"
+		);
+	}
+}
@@ -0,0 +1,58 @@
+//! Runs each program in `tests/programs` with both the interpreter and the JIT
+//! Both have to print what's in the program's `.out` file and exit with 0
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn programs() -> Vec<PathBuf> {
+	let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+	let mut programs = fs::read_dir(dir)
+		.expect("Missing tests/programs")
+		.map(|x| x.unwrap().path())
+		.filter(|x| x.extension().is_some_and(|ext| ext == "srm"))
+		.collect::<Vec<_>>();
+	programs.sort();
+	programs
+}
+
+fn run(args: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_compiler"))
+		.args(args)
+		.output()
+		.expect("Failed to start the compiler")
+}
+
+fn check(path: &Path, how: &str, output: Output, expected: &str) {
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	assert_eq!(
+		output.status.code(),
+		Some(0),
+		"{} exited with {} when run {}\n{}{}",
+		path.display(),
+		output.status,
+		how,
+		stdout,
+		stderr
+	);
+	assert_eq!(
+		stdout,
+		expected,
+		"{} printed the wrong output when run {}",
+		path.display(),
+		how
+	);
+}
+
+#[test]
+fn interpreter_and_jit_agree() {
+	let programs = programs();
+	assert!(!programs.is_empty());
+	for path in programs {
+		let expected = fs::read_to_string(path.with_extension("out"))
+			.unwrap_or_else(|_| panic!("Missing the expected output for {}", path.display()));
+		let file = path.to_str().unwrap();
+		check(&path, "with -i", run(&[file, "-i"]), &expected);
+		check(&path, "with the JIT", run(&["run", file]), &expected);
+	}
+}
@@ -46,10 +46,14 @@ pub fn get_completion_of(
 			false => false,
 		},
 		Call(callee, _, args) => {
-			definition_map.insert(
-				callee.name.clone(),
-				ImCompleteCompletionItem::Variable(callee.name.clone()),
-			);
+			if let Read(callee) = callee.expression.as_ref() {
+				definition_map.insert(
+					callee.name.clone(),
+					ImCompleteCompletionItem::Variable(callee.name.clone()),
+				);
+			} else if !get_completion_of(callee, definition_map, ident_offset) {
+				return false;
+			}
 			for expr in args {
 				match get_completion_of(&expr, definition_map, ident_offset) {
 					true => continue,
@@ -10,41 +10,56 @@ macro_rules! find_in_list {
 	};
 }
 
-pub fn hover(ast: &ExpEnvironment, pos: u32) -> Option<CompType> {
+/// A variable's type followed by the doc comment on its declaration
+fn describe(var: &CompVariable) -> String {
+	match &var.doc {
+		Some(doc) => format!("{}\n\n{}", var.typing, doc),
+		None => var.typing.to_string(),
+	}
+}
+
+pub fn hover(ast: &ExpEnvironment, pos: u32) -> Option<String> {
 	use CompExpression::*;
 	if !ast.located.contains(&(pos as usize)) {
 		return None;
 	};
 	match ast.expression.as_ref() {
-		Read(var) => return Some(var.typing.clone()),
-		Call(var, _, args) => {
+		Read(var) => return Some(describe(var)),
+		Call(callee, _, args) => {
 			find_in_list!(args, pos);
-			return Some(var.typing.clone());
+			return hover(callee, pos);
 		}
 		Array(elements) => find_in_list!(elements, pos),
+		Template(parts) => find_in_list!(parts, pos),
 		List(expressions) => find_in_list!(expressions, pos),
-		Assign(lhs, rhs) => find_in_list!([lhs, rhs], pos),
+		// Anywhere outside the value is the name being declared or assigned to
+		Assign(lhs, rhs) => {
+			find_in_list!([rhs], pos);
+			return Some(describe(&lhs.variable));
+		}
 		WhileLoop { cond, body } => find_in_list!([cond, body], pos),
 		IfElse(if_exp) => find_in_list!([&if_exp.cond, &if_exp.then, &if_exp.otherwise], pos),
 		Index(arr, index) => find_in_list!([arr, index], pos),
 		BinOp(_, lhs, rhs) => find_in_list!([lhs, rhs], pos),
 		OneOp(_, exp) => return hover(exp, pos),
 		Typeof(exp) => return hover(exp, pos),
+		Return(exp) => return hover(exp, pos),
+		Variant(_, _, exp) | Tag(exp) | Payload(exp, _) | Is(exp, _) => return hover(exp, pos),
 		DotAccess(obj, prop) => match prop.1.contains(&(pos as usize)) {
-			true => return Some(ast.result_type.clone()),
+			true => return Some(ast.result_type.to_string()),
 			false => return hover(obj, pos),
 		},
 		Value(CompData::Func(func)) => return hover(&func.body.as_ref()?.body, pos),
 		Struct(data) => {
 			for (key, val) in data {
 				if key.1.contains(&(pos as usize)) {
-					return Some(val.result_type.clone());
+					return Some(val.result_type.to_string());
 				} else if val.located.contains(&(pos as usize)) {
 					return hover(val, pos);
 				}
 			}
 		}
-		Value(_) => {}
+		Value(_) | Break | Continue => {}
 	};
 	None
 }
@@ -11,6 +11,9 @@ pub fn get_definition_of_expr(
 	use CompExpression::*;
 	match expr.expression.as_ref() {
 		Typeof(var) => get_definition_of_expr(var, ident_offset),
+		Variant(_, _, exp) | Tag(exp) | Payload(exp, _) | Is(exp, _) => {
+			get_definition_of_expr(exp, ident_offset)
+		}
 		Read(local) => {
 			if ident_offset >= expr.located.start && ident_offset < expr.located.end {
 				(
@@ -31,14 +34,9 @@ pub fn get_definition_of_expr(
 			(true, Some(value)) | (false, Some(value)) => (false, Some(value)),
 		},
 		Call(callee, _, args) => {
-			if let Some(loc) = &callee.declared_at {
-				let call_end = args
-					.get(0)
-					.map(|x| x.located.start)
-					.unwrap_or(expr.located.end);
-				if ident_offset >= expr.located.start && ident_offset < call_end {
-					return (false, Some((callee.name.clone(), loc.clone())));
-				};
+			let res = get_definition_of_expr(callee, ident_offset);
+			if res.1.is_some() {
+				return res;
 			}
 			for expr in args {
 				let res = get_definition_of_expr(expr, ident_offset);
@@ -18,7 +18,7 @@ use std::sync::Arc;
 use stream::ast1::*;
 use stream::errors::CompError;
 use stream::runner::*;
-use stream::settings::Settings;
+use stream::settings::{OptLevel, Settings};
 use tower_lsp::jsonrpc::Error;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
@@ -176,12 +176,16 @@ impl LanguageServer for Backend {
 		let ast = match self.ast_map.get(uri.path()) {
 			Some(ast) => ast.clone(),
 			None => {
-				parse(create_settings(&TextDocumentItem {
+				let ast = parse(create_settings(&TextDocumentItem {
 					uri: uri.clone(),
 					text: String::default(),
 					version: 0,
 				}))
-				.0
+				.0;
+				match ast {
+					Some(ast) => ast,
+					None => return Ok(None),
+				}
 			}
 		};
 
@@ -372,7 +376,14 @@ impl Backend {
 					line: pos.1.line as u32,
 					character: pos.1.column as u32,
 				};
-				Diagnostic::new_simple(Range::new(start_position, end_position), message)
+				let mut diagnostic =
+					Diagnostic::new_simple(Range::new(start_position, end_position), message);
+				diagnostic.severity = Some(if item.is_warning() {
+					DiagnosticSeverity::WARNING
+				} else {
+					DiagnosticSeverity::ERROR
+				});
+				diagnostic
 			})
 			.collect::<Vec<_>>();
 
@@ -380,7 +391,10 @@ impl Backend {
 			.publish_diagnostics(params.uri.clone(), diagnostics, Some(params.version))
 			.await;
 
-		self.ast_map.insert(params.uri.to_string(), ast);
+		// Keep the last ast that could be built so hovering still works while the file is broken
+		if let Some(ast) = ast {
+			self.ast_map.insert(params.uri.to_string(), ast);
+		}
 		self.client
 			.log_message(MessageType::INFO, &format!("{:?}", semantic_tokens))
 			.await;
@@ -411,30 +425,36 @@ fn offset_to_position(offset: usize, rope: &Rope) -> Option<Position> {
 	Some(Position::new(line as u32, column as u32))
 }
 
-fn parse(settings: Settings) -> (ExpEnvironment, Vec<CompError>, Vec<ImCompleteSemanticToken>) {
+/// The ast is missing if the file couldn't be read or was too broken to parse
+fn parse(
+	settings: Settings,
+) -> (
+	Option<ExpEnvironment>,
+	Vec<CompError>,
+	Vec<ImCompleteSemanticToken>,
+) {
 	let name = settings.input_name.clone();
 	let mut files = parse_files(settings, HashMap::new());
 	transform_files(&name, &mut files);
-	(
-		files
-			.get(&name)
-			.as_ref()
-			.unwrap()
-			.program
-			.as_ref()
-			.unwrap()
-			.body
-			.clone(),
-		files.get(&name).as_ref().unwrap().errors.clone(),
-		Vec::new(),
-	)
+	match files.get(&name) {
+		Some(file) => (
+			file.program.as_ref().map(|prog| prog.body.clone()),
+			file.errors.clone(),
+			Vec::new(),
+		),
+		None => (None, Vec::new(), Vec::new()),
+	}
 }
 fn create_settings(document: &TextDocumentItem) -> Settings {
 	Settings {
 		print_llvm: false,
-		skip_optimizations: true,
+		opt_level: OptLevel::O0,
 		call_linker: false,
 		input_name: document.uri.path().to_string(),
 		object_name: String::new(),
+		target_triple: None,
+		cpu: None,
+		cpu_features: None,
+		emit: Vec::new(),
 	}
 }
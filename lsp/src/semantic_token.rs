@@ -59,20 +59,8 @@ pub fn semantic_token_from_expr(
 			semantic_token_from_expr(lhs, semantic_tokens);
 			semantic_token_from_expr(rhs, semantic_tokens);
 		}
-		Call(var, _, params) => {
-			semantic_tokens.push(ImCompleteSemanticToken {
-				start: expr.located.start,
-				length: expr.located.start
-					- params
-						.first()
-						.map(|x| x.located.start)
-						.unwrap_or(expr.located.end),
-				token_type: LEGEND_TYPE
-					.iter()
-					.position(|item| item == &SemanticTokenType::VARIABLE)
-					.unwrap(),
-			});
-
+		Call(callee, _, params) => {
+			semantic_token_from_expr(callee, semantic_tokens);
 			params.iter().for_each(|p| {
 				semantic_token_from_expr(p, semantic_tokens);
 			});
@@ -0,0 +1,232 @@
+#![feature(let_chains)]
+use chumsky::Parser;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use stream::ast1::*;
+use stream::ast2::*;
+use stream::errors::CompError;
+use stream::interpret::Interpreter;
+use stream::lexer::{lexer, Token};
+use stream::runner::*;
+use stream::settings::{OptLevel, Settings};
+
+const HELP: &str = "Enter expressions or declarations to evaluate them
+:type <expr>    Show the type of an expression without running it
+:ast <expr>     Show the parsed syntax tree of an expression
+:load <file>    Run a file, keeping everything it declares
+:help           Show this message
+:quit           Exit the repl";
+
+struct Repl {
+	/// Kept alive between inputs so declarations accumulate
+	scope: Scope,
+	interpreter: Interpreter,
+	settings: Settings,
+	/// Every input so far, so errors can point back at earlier declarations
+	history: HashMap<String, ImportMap>,
+}
+
+impl Repl {
+	fn new() -> Self {
+		Repl {
+			scope: Scope::get_global_scope(),
+			interpreter: Interpreter::new(),
+			settings: Settings {
+				print_llvm: false,
+				opt_level: OptLevel::O0,
+				call_linker: false,
+				input_name: "<repl>".to_string(),
+				object_name: String::new(),
+				target_triple: None,
+				cpu: None,
+				cpu_features: None,
+				emit: Vec::new(),
+			},
+			history: HashMap::new(),
+		}
+	}
+
+	fn handle(&mut self, input: &str) -> Result<(), String> {
+		let (command, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+		match command {
+			":type" | ":t" => {
+				let parsed = self.parse(rest)?;
+				let prog = self.check(parsed, &mut self.scope.create_child(Vec::new()))?;
+				println!("{}", prog.body.result_type);
+			}
+			":ast" => println!("{:#?}", self.parse(rest)?.1),
+			":load" | ":l" => {
+				let src = fs::read_to_string(rest.trim())
+					.map_err(|x| format!("Could not read '{}': {}", rest.trim(), x))?;
+				self.eval(&src)?;
+			}
+			":help" | ":h" => println!("{}", HELP),
+			":quit" | ":q" => std::process::exit(0),
+			x if x.starts_with(':') => return Err(format!("Unknown command '{}'", x)),
+			_ => self.eval(input)?,
+		}
+		Ok(())
+	}
+
+	/// Type checks the input in the given scope, returning any errors found as a message
+	fn check(
+		&mut self,
+		(imports, ast): (Vec<ImportFrom>, SpannedExpression),
+		scope: &mut Scope,
+	) -> Result<Program, String> {
+		self.import(imports, scope)?;
+		let prog = create_program(&ast, scope, &self.settings);
+		if prog.errors.iter().all(CompError::is_warning) {
+			if !prog.errors.is_empty() {
+				eprintln!("{}", self.render_errors(prog.errors));
+			}
+			Ok(prog.data)
+		} else {
+			Err(self.render_errors(prog.errors))
+		}
+	}
+
+	fn eval(&mut self, src: &str) -> Result<(), String> {
+		let parsed = self.parse(src)?;
+		// Declarations don't produce anything worth showing
+		let is_declaration = matches!(
+			&parsed.1 .1,
+			Expression::Block(exps) if matches!(
+				exps.last(),
+				None | Some((
+					_,
+					Expression::InitAssign(..)
+						| Expression::TypeDeclaration(..)
+						| Expression::Impl(..)
+						| Expression::EnumDeclaration(..)
+				))
+			)
+		);
+		let snapshot = self.scope.snapshot();
+		let prog = match self.check(parsed, &mut self.scope.clone()) {
+			Ok(prog) => prog,
+			Err(msg) => {
+				// The input never runs, so nothing it declared should stick around
+				self.scope.restore(snapshot);
+				return Err(msg);
+			}
+		};
+		let val = self.interpreter.run_program(&prog)?;
+		io::stdout().flush().map_err(|x| x.to_string())?;
+		if !is_declaration {
+			println!("{}: {}", val, prog.body.result_type.widen());
+		}
+		Ok(())
+	}
+
+	/// Parses the input, naming it so it can be referred to by later errors
+	fn parse(&mut self, src: &str) -> Result<(Vec<ImportFrom>, SpannedExpression), String> {
+		self.settings.input_name = format!("<repl:{}>", self.history.len() + 1);
+		let file = ImportMap::new(self.settings.clone()).text(src.to_string());
+		self.history.insert(file.file.clone(), file);
+		match parse_text(src) {
+			(Some(ast), errs) if errs.is_empty() => Ok(ast),
+			(_, errs) => Err(self.render_errors(errs)),
+		}
+	}
+
+	/// Renders errors found in the latest input
+	fn render_errors(&self, errors: Vec<CompError>) -> String {
+		let file = ImportMap {
+			errors,
+			..self.history[&self.settings.input_name].clone()
+		};
+		render_errors(&file, &self.history)
+	}
+
+	/// Runs imported files and brings their exported variables into scope
+	fn import(&mut self, imports: Vec<ImportFrom>, scope: &mut Scope) -> Result<(), String> {
+		for import in imports {
+			let name = resolve_path(&import.file)
+				.ok_or_else(|| format!("Could not find a module at '{}'", import.file))?;
+			let settings = Settings {
+				input_name: name.clone(),
+				..self.settings.clone()
+			};
+			let mut files = parse_files(settings, HashMap::new());
+			transform_files(&name, &mut files);
+			for file in files.values() {
+				if file.has_errors() {
+					return Err(render_errors(file, &files));
+				}
+			}
+			self.interpreter.load_file(&name, &files)?;
+			let exported = files
+				.get(&name)
+				.and_then(|file| file.program.as_ref())
+				.map(Program::get_exported)
+				.unwrap_or_default();
+			for var in exported {
+				let wanted = match &import.imports {
+					Import::All(_) => true,
+					Import::Specific(names) => names.iter().any(|x| x.0 == var.get_name()),
+				};
+				if wanted {
+					scope.add_variable(var);
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+fn render_errors(file: &ImportMap, files: &HashMap<String, ImportMap>) -> String {
+	let colour = io::stderr().is_terminal();
+	file.errors
+		.iter()
+		.map(|e| e.render(file, files, colour))
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+/// Whether the input stops part way through, so it should carry on over multiple lines
+fn is_unfinished(src: &str) -> bool {
+	let (tokens, errs) = lexer().parse_recovery(src);
+	// Running out of input inside a string or template means there's more to come
+	if errs.iter().any(|e| e.found().is_none()) {
+		return true;
+	}
+	// Brackets are counted from tokens so ones inside strings and comments are left out
+	let depth: i32 = tokens
+		.unwrap_or_default()
+		.iter()
+		.map(|(token, _)| match token {
+			Token::StartBlock | Token::StartBracket | Token::StartArray => 1,
+			Token::StartInterpolation => 1,
+			Token::EndBlock | Token::EndBracket | Token::EndArray => -1,
+			Token::EndInterpolation => -1,
+			_ => 0,
+		})
+		.sum();
+	depth > 0
+}
+
+fn main() {
+	let mut repl = Repl::new();
+	let stdin = io::stdin();
+	let mut input = String::new();
+	println!("Stream repl, enter :help for a list of commands");
+	loop {
+		print!("{}", if input.is_empty() { "> " } else { "... " });
+		io::stdout().flush().expect("Failed to write to stdout");
+		let mut line = String::new();
+		if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+			break;
+		}
+		input.push_str(&line);
+		if is_unfinished(&input) {
+			continue;
+		}
+		let trimmed = input.trim();
+		if !trimmed.is_empty() && let Err(msg) = repl.handle(trimmed) {
+			eprintln!("{}", msg);
+		}
+		input.clear();
+	}
+}
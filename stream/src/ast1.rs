@@ -11,6 +11,7 @@ pub enum Symbol {
 #[derive(Clone, Debug, PartialEq)]
 pub enum RawData {
 	Str(String),
+	Char(u8),
 	Bool(bool),
 	Int(i32),
 	Float(f32),
@@ -36,13 +37,32 @@ pub type SpannedExpression = (Range<usize>, Expression);
 pub enum Expression {
 	DotAccess(Box<SpannedExpression>, (String, Range<usize>)),
 	Struct(Vec<((String, Range<usize>), SpannedExpression)>),
-	// The name of the new type, a list of named generics with optional type constraints and the shape of the new type
-	TypeDeclaration(String, Vec<(String, Option<CustomType>)>, CustomType),
+	// The name of the new type, a list of named generics with optional type constraints, the shape of the new type and its doc comment
+	TypeDeclaration(
+		String,
+		Vec<(String, Option<CustomType>)>,
+		CustomType,
+		Option<String>,
+	),
+	// The name of the enum, each variant with the struct it carries and the doc comment
+	// Variants like `Circle(Float)` carry a struct with fields named by position
+	EnumDeclaration(
+		String,
+		Vec<((String, Range<usize>), CustomType)>,
+		Option<String>,
+	),
+	// The value being matched and the arms it's checked against in order
+	Match(Box<SpannedExpression>, Vec<MatchArm>),
 	Typeof(Box<SpannedExpression>),
 	Array(Vec<SpannedExpression>),
 	BinOp(Op, Box<SpannedExpression>, Box<SpannedExpression>),
 	Terminal(Symbol),
-	FuncCall(String, Vec<CustomType>, Vec<SpannedExpression>),
+	// The expression being called, its generic arguments and its arguments
+	FuncCall(
+		Box<SpannedExpression>,
+		Vec<CustomType>,
+		Vec<SpannedExpression>,
+	),
 	Block(Vec<SpannedExpression>),
 	IfElse(
 		Box<SpannedExpression>,
@@ -50,17 +70,73 @@ pub enum Expression {
 		Box<SpannedExpression>,
 	),
 	Loop(Box<SpannedExpression>, Box<SpannedExpression>),
+	// The initialiser, condition and step of a C style for loop, then its body
+	ForLoop(
+		Box<SpannedExpression>,
+		Box<SpannedExpression>,
+		Box<SpannedExpression>,
+		Box<SpannedExpression>,
+	),
+	// Whether the loop variable is constant, its name, the array or string it goes through and the body
+	ForOf(
+		bool,
+		(String, Range<usize>),
+		Box<SpannedExpression>,
+		Box<SpannedExpression>,
+	),
+	Break,
+	Continue,
+	Return(Option<Box<SpannedExpression>>),
 	Invalid,
 	Assign(Box<SpannedExpression>, Box<SpannedExpression>),
+	// The operator combining the current value with the new one, the target, the new value and whether the value from before the update is given back like with `x++`
+	CompoundAssign(Op, Box<SpannedExpression>, Box<SpannedExpression>, bool),
+	// Whether it's external, whether it's constant, the name, the declared type, the value and the doc comment
 	InitAssign(
 		bool,
 		bool,
 		(String, Range<usize>),
 		Option<CustomType>,
 		Box<SpannedExpression>,
+		Option<String>,
 	),
 	Index(Box<SpannedExpression>, Box<SpannedExpression>),
+	// The name of the type the methods are for, its generics and each method with its doc comment
+	Impl(
+		(String, Range<usize>),
+		Vec<(String, Option<CustomType>)>,
+		Vec<((String, Range<usize>), Function, Option<String>)>,
+	),
 	Conversion(Box<SpannedExpression>, CustomType),
+	OneOp(Prefix, Box<SpannedExpression>),
+	// The text and interpolated expressions that make up a template string, in order
+	Template(Vec<SpannedExpression>),
+}
+
+pub type SpannedPattern = (Range<usize>, Pattern);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pattern {
+	/// `_`, which matches anything without binding it
+	Wildcard,
+	/// Matches anything and binds it to a new variable
+	Binding(String),
+	Literal(RawData),
+	/// `n: Int`, which only matches values of the type and binds them unless the name is `_`
+	Typed(Option<String>, CustomType),
+	/// An enum's name, the variant and patterns for the fields of its payload
+	Variant(
+		String,
+		(String, Range<usize>),
+		Vec<(String, SpannedPattern)>,
+	),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchArm {
+	pub pattern: SpannedPattern,
+	pub guard: Option<SpannedExpression>,
+	pub body: SpannedExpression,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -122,6 +198,7 @@ impl CustomTypeStruct {
 #[derive(Debug, PartialEq, Clone)]
 pub enum ConstantData {
 	Str(String),
+	Char(u8),
 	Int(i32),
 	Float(f32),
 	Bool(bool),
@@ -149,6 +226,10 @@ impl hash::Hash for ConstantData {
 				hasher.write_u8(3);
 				hasher.write_u8(*b as u8);
 			}
+			Char(c) => {
+				hasher.write_u8(4);
+				hasher.write_u8(*c);
+			}
 		}
 	}
 }
@@ -158,10 +239,20 @@ pub enum Op {
 	Sub,
 	Div,
 	Mult,
+	Mod,
 	Eq,
 	Neq,
+	Lt,
+	Gt,
 	Le,
 	Ge,
+	And,
+	Or,
+	BitAnd,
+	BitOr,
+	BitXor,
+	Shl,
+	Shr,
 }
 
 impl Op {
@@ -169,3 +260,10 @@ impl Op {
 		format!("{}", self)
 	}
 }
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Prefix {
+	Neg,
+	Not,
+	BitNot,
+}
@@ -32,6 +32,8 @@ pub enum CompType {
 	Constant(ConstantData),
 	Touple(Vec<Self>),
 	Char,
+	/// A nominal type made of its name and each variant with the struct it carries
+	Enum(String, Vec<(String, CompType)>),
 }
 
 impl CompType {
@@ -44,6 +46,7 @@ impl CompType {
 				Float | Constant(ConstantData::Float(_)) => Float,
 				Null | Constant(ConstantData::Null) => Null,
 				Bool | Constant(ConstantData::Bool(_)) => Bool,
+				Char | Constant(ConstantData::Char(_)) => Char,
 				Constant(ConstantData::Str(str)) => {
 					Str(ConstantData::Int(str.len() as i32).to_type().boxed())
 				}
@@ -62,7 +65,7 @@ impl CompType {
 		match self {
 			Generic(_, _) => true,
 			IntPtr | Type | Char | Unknown | Bool | Ptr | Int | Float | Null | Constant(_)
-			| Not(_) => false,
+			| Not(_) | Enum(_, _) => false,
 			Array(element_ty, _) => element_ty.contains_generic(),
 			Touple(elements) => elements.iter().any(|x| x.contains_generic()),
 			Struct(elements) => elements.iter().any(|x| x.1.contains_generic()),
@@ -83,6 +86,7 @@ impl CompType {
 		match self {
 			Self::Constant(data) => data.widen(),
 			Self::Array(el_ty, len) => Self::Array(el_ty.widen().boxed(), len.clone()),
+			Self::Union(types) => Self::Union(map_vec!(types, |x| x.widen())).flatten(),
 			x => x.clone(),
 		}
 	}
@@ -91,6 +95,25 @@ impl CompType {
 		format!("{}", self)
 	}
 
+	/// The most characters it can take to write a value of this type into a template string
+	pub fn max_text_len(&self) -> Option<i32> {
+		use CompType::*;
+		match self.widen() {
+			Str(len) => match len.as_ref() {
+				Constant(ConstantData::Int(len)) => Some(*len),
+				_ => None,
+			},
+			// Written with `%g` which uses at most 6 significant figures, like -1.17549e-38
+			Float => Some(12),
+			Int => Some(11),
+			Bool => Some(5),
+			Null => Some(4),
+			Char => Some(1),
+			Generic(_, ty) => ty.max_text_len(),
+			_ => None,
+		}
+	}
+
 	pub fn get_variants(&self) -> Vec<CompType> {
 		if let CompType::Union(types) = self {
 			types.clone()
@@ -108,9 +131,11 @@ impl CompType {
 			extends.super_type_of(extends_type)
 		} else if self.is_ptr() && sub_type.is_str() {
 			true
+		} else if let CompType::Union(types) = sub_type {
+			// A value of the union could hold any of its variants, so every one has to fit
+			types.iter().all(|ty| self.super_type_of(ty))
 		} else if let CompType::Union(types) = self {
-			// TODO: Do deeper checking here
-			types.contains(sub_type)
+			types.iter().any(|ty| ty.super_type_of(sub_type))
 		} else if let CompType::Constant(data) = sub_type {
 			match (self, data) {
 				(CompType::Str(len), ConstantData::Str(str)) => match len.as_ref() {
@@ -122,8 +147,13 @@ impl CompType {
 				(CompType::Float, ConstantData::Float(_)) => true,
 				(CompType::Bool, ConstantData::Bool(_)) => true,
 				(CompType::Null, ConstantData::Null) => true,
+				(CompType::Char, ConstantData::Char(_)) => true,
 				_ => false,
 			}
+		} else if let (Self::Str(len), Self::Str(sub_len)) = (self, sub_type) {
+			len.super_type_of(sub_len)
+		} else if let (Self::Array(el, len), Self::Array(sub_el, sub_len)) = (self, sub_type) {
+			el.super_type_of(sub_el) && len.super_type_of(sub_len)
 		} else if let (Self::Struct(data), Self::Struct(sub)) = (self, sub_type) {
 			for (a, b) in data.iter().zip(sub) {
 				if !a.1.super_type_of(b.1) {
@@ -152,7 +182,7 @@ impl CompType {
 
 		let ty = match self {
 			IntPtr | Ptr | Type | Not(_) | Unknown | Char | Bool | Constant(_) | Null | Int
-			| Float => self.clone(),
+			| Float | Enum(_, _) => self.clone(),
 			Str(len) => Str(len
 				.substitute_generics(generics, location.clone())
 				.collect_errors_into(&mut errors)
@@ -248,6 +278,41 @@ impl CompType {
 		matches
 	}
 
+	/// Works out what each generic in this type has to be to match the given type
+	/// Generics that can't be worked out are left as unknown
+	pub fn infer_generics(&self, ty: &CompType, found: &mut Vec<CompType>) {
+		use CompType::*;
+		match (self, ty) {
+			(Generic(pos, _), ty) => {
+				if found.len() <= *pos {
+					found.resize(pos + 1, Unknown);
+				}
+				if found[*pos] == Unknown {
+					found[*pos] = ty.clone();
+				}
+			}
+			(Struct(a), Struct(b)) => {
+				for (key, a) in a {
+					if let Some(b) = b.get(key) {
+						a.infer_generics(b, found)
+					}
+				}
+			}
+			(Array(a, a_len), Array(b, b_len)) => {
+				a.infer_generics(b, found);
+				a_len.infer_generics(b_len, found)
+			}
+			(Str(a), Str(b)) => a.infer_generics(b, found),
+			(Callible(a_args, a_ret), Callible(b_args, b_ret)) => {
+				for (a, b) in a_args.iter().zip(b_args) {
+					a.infer_generics(b, found)
+				}
+				a_ret.infer_generics(b_ret, found)
+			}
+			_ => {}
+		}
+	}
+
 	pub fn is_bool(&self) -> bool {
 		matches!(
 			*self,
@@ -266,6 +331,13 @@ impl CompType {
 		)
 	}
 
+	pub fn is_char(&self) -> bool {
+		matches!(
+			*self,
+			CompType::Char | CompType::Constant(ConstantData::Char(_))
+		)
+	}
+
 	pub fn is_int_ptr(&self) -> bool {
 		matches!(*self, CompType::IntPtr)
 	}
@@ -298,6 +370,7 @@ impl CompType {
 		match self {
 			Char => Char,
 			IntPtr => IntPtr,
+			Enum(_, _) => self.clone(),
 			Touple(elements) => Touple(map_vec!(elements, |el| el.flatten())),
 			Constant(data) => Constant(data.clone()),
 			Struct(fields) => {
@@ -328,9 +401,14 @@ impl CompType {
 				Box::new(ret.flatten()),
 			),
 			Union(types) => {
-				let types = types.iter().map(|x| x.flatten()).collect::<Vec<_>>();
+				// Nested unions are merged in, which also drops any that can never happen
+				let types = types
+					.iter()
+					.map(|x| x.flatten())
+					.flat_map(|x| x.get_variants())
+					.collect::<Vec<_>>();
 				match types.len() {
-					0 => Null,
+					0 => NEVER,
 					1 => types[0].clone(),
 					_ => {
 						let mut uniques = Vec::new();
@@ -358,6 +436,7 @@ impl Display for CompType {
 		match self {
 			Char => write!(f, "Char"),
 			IntPtr => write!(f, "IntPtr"),
+			Enum(name, _) => write!(f, "{}", name),
 			Touple(elements) => write!(
 				f,
 				"[{}]",
@@ -393,7 +472,7 @@ impl Display for CompType {
 					.iter()
 					.map(CompType::get_str)
 					.reduce(|a, b| format!("{} | {}", a, b))
-					.unwrap()
+					.unwrap_or_else(|| "Never".to_string())
 			),
 			Callible(args, ret) => write!(
 				f,
@@ -429,8 +508,8 @@ pub enum CompExpression {
 	Read(CompVariable),
 	/// An unary operation
 	OneOp(Prefix, ExpEnvironment),
-	/// Calling a function
-	Call(CompVariable, Vec<CompType>, Vec<ExpEnvironment>),
+	/// Calling a function, which can be any expression that gives back a function
+	Call(ExpEnvironment, Vec<CompType>, Vec<ExpEnvironment>),
 	/// Assigning to something
 	Assign(MemoryLocation, ExpEnvironment),
 	/// Branching code based on some condition
@@ -446,6 +525,22 @@ pub enum CompExpression {
 	List(Vec<ExpEnvironment>),
 	/// Converting the return type of an expression to a specified type
 	Conversion(ExpEnvironment, CompType),
+	/// Building a string from text and the values put into it
+	Template(Vec<ExpEnvironment>),
+	/// Leaving the innermost loop
+	Break,
+	/// Skipping to the next iteration of the innermost loop
+	Continue,
+	/// Leaving the function early with a value
+	Return(ExpEnvironment),
+	/// Creating a value of an enum from the variant's position and its payload
+	Variant(CompType, usize, ExpEnvironment),
+	/// The position of the variant an enum value holds
+	Tag(ExpEnvironment),
+	/// The payload of an enum value, which must be holding the variant at the given position
+	Payload(ExpEnvironment, usize),
+	/// Whether a value currently holds a variant of its union that the type covers
+	Is(ExpEnvironment, CompType),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -479,6 +574,15 @@ impl ExpEnvironment {
 		matches!(self.expression.as_ref(), CompExpression::Call(_, _, _))
 	}
 
+	/// What to call this expression in error messages
+	pub fn get_name(&self) -> String {
+		match self.expression.as_ref() {
+			CompExpression::Read(var) => var.get_name(),
+			CompExpression::DotAccess(_, (prop, _)) => prop.clone(),
+			_ => "expression".to_string(),
+		}
+	}
+
 	pub fn find_map<'a, T: 'a, F>(&'a self, matcher: &mut F) -> Option<T>
 	where
 		F: FnMut(&'a Self) -> Option<T>,
@@ -490,7 +594,10 @@ impl ExpEnvironment {
 		match self.expression.as_ref() {
 			CompExpression::List(exps)
 			| CompExpression::Array(exps)
-			| CompExpression::Call(_, _, exps) => exps.iter().find_map(|x| x.find_map(matcher)),
+			| CompExpression::Template(exps) => exps.iter().find_map(|x| x.find_map(matcher)),
+			CompExpression::Call(callee, _, exps) => callee
+				.find_map(matcher)
+				.or_else(|| exps.iter().find_map(|x| x.find_map(matcher))),
 			CompExpression::Struct(fields) => fields.iter().find_map(|x| x.1 .1.find_map(matcher)),
 			CompExpression::IfElse(ifelse) => {
 				let cond = ifelse.cond.find_map(matcher);
@@ -523,7 +630,12 @@ impl ExpEnvironment {
 			CompExpression::OneOp(_, exp)
 			| CompExpression::Typeof(exp)
 			| CompExpression::Conversion(exp, _)
-			| CompExpression::DotAccess(exp, _) => exp.find_map(matcher),
+			| CompExpression::DotAccess(exp, _)
+			| CompExpression::Return(exp)
+			| CompExpression::Tag(exp)
+			| CompExpression::Payload(exp, _)
+			| CompExpression::Is(exp, _)
+			| CompExpression::Variant(_, _, exp) => exp.find_map(matcher),
 			CompExpression::Assign(lvalue, rhs) => {
 				let rhs = rhs.find_map(matcher);
 				if rhs.is_some() {
@@ -539,7 +651,10 @@ impl ExpEnvironment {
 				}
 				None
 			}
-			CompExpression::Read(_) | CompExpression::Value(_) => None,
+			CompExpression::Read(_)
+			| CompExpression::Value(_)
+			| CompExpression::Break
+			| CompExpression::Continue => None,
 		}
 	}
 
@@ -568,7 +683,11 @@ impl ExpEnvironment {
 		match self.expression.as_ref() {
 			CompExpression::List(exps)
 			| CompExpression::Array(exps)
-			| CompExpression::Call(_, _, exps) => {
+			| CompExpression::Template(exps) => {
+				current.append(&mut exps.iter().flat_map(|x| x.map_each(mapper)).collect());
+			}
+			CompExpression::Call(callee, _, exps) => {
+				current.append(&mut callee.map_each(mapper));
 				current.append(&mut exps.iter().flat_map(|x| x.map_each(mapper)).collect());
 			}
 			CompExpression::Struct(fields) => {
@@ -593,7 +712,12 @@ impl ExpEnvironment {
 			CompExpression::OneOp(_, exp)
 			| CompExpression::Typeof(exp)
 			| CompExpression::Conversion(exp, _)
-			| CompExpression::DotAccess(exp, _) => {
+			| CompExpression::DotAccess(exp, _)
+			| CompExpression::Return(exp)
+			| CompExpression::Tag(exp)
+			| CompExpression::Payload(exp, _)
+			| CompExpression::Is(exp, _)
+			| CompExpression::Variant(_, _, exp) => {
 				current.append(&mut exp.map_each(mapper));
 			}
 			CompExpression::Assign(lvalue, rhs) => {
@@ -604,7 +728,10 @@ impl ExpEnvironment {
 					}
 				}
 			}
-			CompExpression::Read(_) | CompExpression::Value(_) => {}
+			CompExpression::Read(_)
+			| CompExpression::Value(_)
+			| CompExpression::Break
+			| CompExpression::Continue => {}
 		}
 		current
 	}
@@ -632,7 +759,11 @@ impl ExpEnvironment {
 		match self.expression.as_mut() {
 			CompExpression::List(exps)
 			| CompExpression::Array(exps)
-			| CompExpression::Call(_, _, exps) => exps.iter_mut().for_each(|x| x.map_inplace(mapper)),
+			| CompExpression::Template(exps) => exps.iter_mut().for_each(|x| x.map_inplace(mapper)),
+			CompExpression::Call(callee, _, exps) => {
+				callee.map_inplace(mapper);
+				exps.iter_mut().for_each(|x| x.map_inplace(mapper));
+			}
 			CompExpression::Struct(fields) => {
 				fields.iter_mut().for_each(|x| x.1 .1.map_inplace(mapper))
 			}
@@ -650,7 +781,12 @@ impl ExpEnvironment {
 			CompExpression::OneOp(_, exp)
 			| CompExpression::Typeof(exp)
 			| CompExpression::Conversion(exp, _)
-			| CompExpression::DotAccess(exp, _) => {
+			| CompExpression::DotAccess(exp, _)
+			| CompExpression::Return(exp)
+			| CompExpression::Tag(exp)
+			| CompExpression::Payload(exp, _)
+			| CompExpression::Is(exp, _)
+			| CompExpression::Variant(_, _, exp) => {
 				exp.map_inplace(mapper);
 			}
 			CompExpression::Assign(lvalue, rhs) => {
@@ -661,7 +797,10 @@ impl ExpEnvironment {
 					}
 				}
 			}
-			CompExpression::Read(_) | CompExpression::Value(_) => {}
+			CompExpression::Read(_)
+			| CompExpression::Value(_)
+			| CompExpression::Break
+			| CompExpression::Continue => {}
 		}
 	}
 
@@ -676,6 +815,7 @@ impl ExpEnvironment {
 					typing: x.result_type.clone(),
 					constant: false,
 					external: false,
+					doc: None,
 				};
 				count += 1;
 				let mut list: Vec<ExpEnvironment> = elements
@@ -721,7 +861,11 @@ impl ExpEnvironment {
 	pub fn get_final_expressions(&self) -> Vec<&ExpEnvironment> {
 		match self.expression.as_ref() {
 			CompExpression::List(exps) => exps.last().unwrap().get_final_expressions(),
-			CompExpression::WhileLoop { cond: _, body: _ } => Vec::new(),
+			// Returns are found separately by `FunctionAst::get_returns`
+			CompExpression::WhileLoop { cond: _, body: _ }
+			| CompExpression::Break
+			| CompExpression::Continue
+			| CompExpression::Return(_) => Vec::new(),
 			CompExpression::IfElse(ifelse) => ifelse
 				.then
 				.get_final_expressions()
@@ -729,6 +873,7 @@ impl ExpEnvironment {
 				.chain(ifelse.otherwise.get_final_expressions())
 				.collect(),
 			CompExpression::Array(_)
+			| CompExpression::Template(_)
 			| CompExpression::Struct(_)
 			| CompExpression::Read(_)
 			| CompExpression::Typeof(_)
@@ -739,7 +884,11 @@ impl ExpEnvironment {
 			| CompExpression::Assign(_, _)
 			| CompExpression::Call(_, _, _)
 			| CompExpression::Conversion(_, _)
-			| CompExpression::DotAccess(_, _) => {
+			| CompExpression::DotAccess(_, _)
+			| CompExpression::Variant(_, _, _)
+			| CompExpression::Tag(_)
+			| CompExpression::Payload(_, _)
+			| CompExpression::Is(_, _) => {
 				vec![self]
 			}
 		}
@@ -785,12 +934,19 @@ impl Program {
 	}
 }
 
-#[derive(Debug, PartialEq, Default)]
+#[derive(Debug, Clone, PartialEq, Default)]
 struct InnerScope {
 	parent: Option<Scope>,
 	types: HashMap<String, CompType>,
 	variables: HashMap<String, CompVariable>,
 	preset_variables: HashMap<String, CompVariable>,
+	/// The type returned by the function this is the body of
+	return_type: Option<CompType>,
+	in_loop: bool,
+	/// The hidden variables holding each method, by the name they're called with
+	methods: HashMap<String, Vec<String>>,
+	/// Variables known to only hold some of the variants of their union at this point in the code
+	narrowed: HashMap<String, CompType>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -810,7 +966,7 @@ impl Scope {
 	) -> &'a mut Scope {
 		// TODO: Sort out errors and stuff here
 		match ast {
-			Expression::TypeDeclaration(name, generics, declared_type) => {
+			Expression::TypeDeclaration(name, generics, declared_type, _) => {
 				if self.get_type(name).is_err() {
 					let mut subscope = self.create_child(Vec::new());
 					for (pos, (name, constraint)) in generics.iter().enumerate() {
@@ -837,7 +993,17 @@ impl Scope {
 				}
 				self
 			}
-			Expression::InitAssign(external, constant, name, declared_type, _exp) => {
+			Expression::EnumDeclaration(name, variants, _) => {
+				if self.get_type(name).is_err() {
+					let variants = map_vec!(variants, |((variant, _), payload)| {
+						// Errors in the payloads are reported when the declaration is checked
+						(variant.clone(), transform_type(payload, self, loc.clone()).data)
+					});
+					self.add_type(name.clone(), CompType::Enum(name.clone(), variants));
+				}
+				self
+			}
+			Expression::InitAssign(external, constant, name, declared_type, _exp, doc) => {
 				if self.variable_exists(&name.0) {
 					self
 				} else {
@@ -859,9 +1025,43 @@ impl Scope {
 						initialised: false,
 						external: *external,
 						declared_at: Some((file.to_string(), name.1.clone())),
+						doc: doc.clone(),
 					})
 				}
 			}
+			Expression::Impl((ty, _), generics, methods) => {
+				for ((name, name_loc), method, doc) in methods {
+					let variable = method_name(ty, name);
+					if self.variable_exists(&variable) {
+						continue;
+					}
+					let func = method.as_method(ty, generics);
+					// Any errors in the signature are reported when the method itself is checked
+					let typing = match func.args.iter().map(|x| x.1.clone()).collect() {
+						Some(args) => {
+							let ty = CustomType::Callible(
+								func.generics.clone(),
+								args,
+								Box::new(func.return_type.0.clone()),
+							);
+							transform_type(&ty, self, loc.clone()).data
+						}
+						None => CompType::Unknown,
+					};
+					self.add_variable(CompVariable {
+						name: variable.clone(),
+						constant: true,
+						typing,
+						initialised: false,
+						external: false,
+						declared_at: Some((file.to_string(), name_loc.clone())),
+						doc: doc.clone(),
+					});
+					let mut inner = self.0.borrow_mut();
+					inner.methods.entry(name.clone()).or_default().push(variable);
+				}
+				self
+			}
 			Expression::Block(expressions) => {
 				for exp in expressions {
 					self.resolve_scope(exp, file);
@@ -899,9 +1099,24 @@ impl Scope {
 				.map(|var| (var.get_name(), var))
 				.collect(),
 			variables: HashMap::new(),
+			return_type: None,
+			in_loop: false,
+			methods: HashMap::new(),
+			narrowed: HashMap::new(),
 		})))
 	}
 
+	/// A copy of everything declared directly in this scope, for `restore` to put back
+	pub fn snapshot(&self) -> Scope {
+		Scope(Arc::new(RefCell::new(self.0.borrow().clone())))
+	}
+
+	/// Undoes everything declared in this scope since the snapshot was taken
+	pub fn restore(&mut self, snapshot: Scope) {
+		let inner = snapshot.0.borrow().clone();
+		*self.0.borrow_mut() = inner;
+	}
+
 	pub fn get_exported(&self) -> Vec<CompVariable> {
 		self.0
 			.borrow()
@@ -997,6 +1212,110 @@ impl Scope {
 		}
 	}
 
+	/// Finds a method with the given name that can be called on the receiver
+	/// Gives back the variable holding it and the generics worked out from the receiver
+	pub fn find_method(
+		&self,
+		name: &str,
+		receiver: &CompType,
+	) -> Option<(CompVariable, Vec<CompType>)> {
+		let candidates = self.get_inner().methods.get(name).cloned().unwrap_or_default();
+		for method in candidates.iter().filter_map(|x| self.get_variable(x).ok()) {
+			let self_type = match method.get_type() {
+				CompType::Callible(args, _) => match args.first() {
+					Some(ty) => ty.clone(),
+					None => continue,
+				},
+				_ => return Some((method, Vec::new())),
+			};
+			let mut found = Vec::new();
+			self_type.infer_generics(receiver, &mut found);
+			// The exact types are tried first so constants like array lengths can still match
+			let widened = map_vec!(found, |x| x.widen());
+			for generics in [found, widened] {
+				let expected = self_type.substitute_generics(&generics, 0..0);
+				if expected.errors.is_empty() && expected.data.super_type_of(receiver) {
+					return Some((method, generics));
+				}
+			}
+		}
+		self.get_parent()?.find_method(name, receiver)
+	}
+
+	pub fn set_return_type(&mut self, ty: &CompType) {
+		self.0.borrow_mut().return_type = Some(ty.clone());
+	}
+
+	pub fn get_return_type(&self) -> Option<CompType> {
+		let inner = self.get_inner();
+		match (&inner.return_type, &inner.parent) {
+			(Some(ty), _) => Some(ty.clone()),
+			// Loops can have their own scope inside a function
+			(None, Some(parent)) => parent.get_return_type(),
+			(None, None) => None,
+		}
+	}
+
+	/// Sets whether `break` and `continue` can be used
+	pub fn set_in_loop(&mut self, in_loop: bool) {
+		self.0.borrow_mut().in_loop = in_loop;
+	}
+
+	/// Blocks inside a loop have their own scope, but a function inside one starts outside of it
+	pub fn in_loop(&self) -> bool {
+		let inner = self.get_inner();
+		match (inner.in_loop, &inner.return_type, &inner.parent) {
+			(true, _, _) => true,
+			(false, None, Some(parent)) => parent.in_loop(),
+			_ => false,
+		}
+	}
+
+	/// The type a variable has been narrowed to by the conditions leading to this point
+	/// Narrowing from outside a function doesn't apply inside it since it can be called later
+	pub fn get_narrowed(&self, name: &str) -> Option<CompType> {
+		let inner = self.get_inner();
+		if let Some(ty) = inner.narrowed.get(name) {
+			Some(ty.clone())
+		} else if inner.variables.contains_key(name) || inner.return_type.is_some() {
+			None
+		} else {
+			inner.parent.as_ref()?.get_narrowed(name)
+		}
+	}
+
+	pub fn narrow(&mut self, narrowing: Narrowing) {
+		self.0.borrow_mut().narrowed.extend(narrowing);
+	}
+
+	/// Runs `f` with extra narrowing that only applies inside it
+	/// Anything assigned to inside `f` stays forgotten afterwards
+	pub fn with_narrowing<T>(
+		&mut self,
+		narrowing: Narrowing,
+		f: impl FnOnce(&mut Self) -> T,
+	) -> T {
+		let before = self.get_inner().narrowed.clone();
+		self.narrow(narrowing);
+		let res = f(self);
+		let after = std::mem::take(&mut self.0.borrow_mut().narrowed);
+		self.0.borrow_mut().narrowed =
+			before.into_iter().filter(|(name, _)| after.contains_key(name)).collect();
+		res
+	}
+
+	/// Assigning to a variable means it could hold any variant of its union again
+	pub fn forget_narrowing(&mut self, name: &str) {
+		let mut inner = self.0.borrow_mut();
+		inner.narrowed.remove(name);
+		if !inner.variables.contains_key(name)
+			&& inner.return_type.is_none()
+			&& let Some(parent) = &mut inner.parent
+		{
+			parent.forget_narrowing(name);
+		}
+	}
+
 	pub fn variable_has_type(&self, name: &String) -> bool {
 		if let Some(var) = self.0.borrow().variables.get(name) {
 			var.get_type() != CompType::Unknown
@@ -1016,6 +1335,7 @@ pub enum CompData {
 	Int(i32),
 	Float(f32),
 	Str(String),
+	Char(u8),
 	Func(FunctionAst),
 }
 
@@ -1027,6 +1347,7 @@ impl CompData {
 			Bool(b) => CompType::Constant(ConstantData::Bool(*b)),
 			Int(i) => CompType::Constant(ConstantData::Int(*i)),
 			Float(f) => CompType::Constant(ConstantData::Float(*f)),
+			Char(c) => CompType::Constant(ConstantData::Char(*c)),
 			Str(content) => CompType::Str(Box::new(CompType::Constant(ConstantData::Int(
 				content.len() as i32,
 			)))),
@@ -1050,6 +1371,8 @@ pub struct CompVariable {
 	pub constant: bool,
 	pub external: bool,
 	pub declared_at: Option<(String, Range<usize>)>,
+	/// The doc comment written before the declaration
+	pub doc: Option<String>,
 }
 
 impl CompVariable {
@@ -1175,7 +1498,12 @@ impl FunctionAst {
 		if self.return_type.is_null() {
 			Vec::new()
 		} else if let Some(prog) = &self.body {
-			prog.body.get_final_expressions()
+			let mut returns = prog.body.map_each(&mut |x| match x.expression.as_ref() {
+				CompExpression::Return(exp) => exp.get_final_expressions(),
+				_ => Vec::new(),
+			});
+			returns.append(&mut prog.body.get_final_expressions());
+			returns
 		} else {
 			Vec::new()
 		}
@@ -1186,9 +1514,21 @@ impl Op {
 	pub fn resulting_type(&self, a: &CompType, b: &CompType) -> Result<CompType, CompError> {
 		use CompType::*;
 		use Op::*;
+		// Constrained generics can be used like the type they're constrained to
+		match (a, b) {
+			(Generic(_, a), b) if a.as_ref() != &Unknown => return self.resulting_type(a, b),
+			(a, Generic(_, b)) if b.as_ref() != &Unknown => return self.resulting_type(a, b),
+			_ => {}
+		}
 		match self {
+			Eq | Neq if !Self::union_comparable(a, b) => Err(self.invalid_comparison_msg(a, b)),
 			Eq | Neq => Ok(Bool),
-			Le | Ge => match (a, b) {
+			// Operands that failed to type check have already been reported
+			_ if a == &Unknown || b == &Unknown => Ok(Unknown),
+			Lt | Gt | Le | Ge | Add | Sub if a.is_char() || b.is_char() => {
+				self.char_resulting_type(a, b)
+			}
+			Lt | Gt | Le | Ge => match (a, b) {
 				(Float, Float) | (Int, Int) => Ok(Bool),
 				(Float, Constant(ConstantData::Float(_)))
 				| (Constant(ConstantData::Float(_)), Float) => Ok(Bool),
@@ -1196,18 +1536,10 @@ impl Op {
 					Ok(Bool)
 				}
 				(Constant(ConstantData::Float(a)), Constant(ConstantData::Float(b))) => {
-					Ok(Constant(ConstantData::Bool(if self == &Le {
-						a < b
-					} else {
-						a > b
-					})))
+					Ok(Constant(ConstantData::Bool(self.compare(a, b))))
 				}
 				(Constant(ConstantData::Int(a)), Constant(ConstantData::Int(b))) => {
-					Ok(Constant(ConstantData::Bool(if self == &Le {
-						a < b
-					} else {
-						a > b
-					})))
+					Ok(Constant(ConstantData::Bool(self.compare(a, b))))
 				}
 				(Int, Generic(_, ty)) | (Generic(_, ty), Int) => {
 					if ty.is_int() {
@@ -1248,7 +1580,7 @@ impl Op {
 				(Int, Constant(ConstantData::Int(_))) => Int,
 				(Constant(ConstantData::Int(_)), Int) => Int,
 				(Constant(ConstantData::Int(a)), Constant(ConstantData::Int(b))) => {
-					Constant(ConstantData::Int(a + b))
+					a.checked_add(*b).map_or(Int, |x| Constant(ConstantData::Int(x)))
 				}
 				(Float, Float) => Float,
 				(Float, Constant(ConstantData::Float(_))) => Float,
@@ -1264,7 +1596,7 @@ impl Op {
 				| (Constant(ConstantData::Int(_)), Int)
 				| (Generic(_, _), Constant(ConstantData::Int(_))) => Ok(Int),
 				(Constant(ConstantData::Int(a)), Constant(ConstantData::Int(b))) => {
-					Ok(Constant(ConstantData::Int(a * b)))
+					Ok(a.checked_mul(*b).map_or(Int, |x| Constant(ConstantData::Int(x))))
 				}
 				(Float, Float) => Ok(Float),
 				(Float, Constant(ConstantData::Float(_)))
@@ -1274,7 +1606,7 @@ impl Op {
 				}
 				_ => Err(self.invalid_comparison_msg(a, b)),
 			},
-			Sub | Div => match (a, b) {
+			Sub | Div | Mod => match (a, b) {
 				(Int, Int) => Ok(Int),
 				(Float, Float) => Ok(Float),
 				(Float, Constant(ConstantData::Float(_)))
@@ -1285,23 +1617,98 @@ impl Op {
 				(Constant(ConstantData::Float(a)), Constant(ConstantData::Float(b))) => {
 					Ok(Constant(ConstantData::Float(if self == &Sub {
 						a - b
-					} else if self == &Mult {
-						a * b
+					} else if self == &Mod {
+						a % b
 					} else {
 						a / b
 					})))
 				}
+				// Overflowing and dividing by zero are left to happen when the program is run
 				(Constant(ConstantData::Int(a)), Constant(ConstantData::Int(b))) => {
-					Ok(Constant(ConstantData::Int(if self == &Sub {
-						a - b
-					} else if self == &Mult {
-						a * b
+					let folded = match self {
+						Sub => a.checked_sub(*b),
+						Mod => a.checked_rem(*b),
+						_ => a.checked_div(*b),
+					};
+					Ok(folded.map_or(Int, |x| Constant(ConstantData::Int(x))))
+				}
+				_ => Err(self.invalid_comparison_msg(a, b)),
+			},
+			And | Or => match (a, b) {
+				(Constant(ConstantData::Bool(a)), Constant(ConstantData::Bool(b))) => {
+					Ok(Constant(ConstantData::Bool(if self == &And {
+						*a && *b
 					} else {
-						a / b
+						*a || *b
 					})))
 				}
+				(a, b) if a.is_bool() && b.is_bool() => Ok(Bool),
 				_ => Err(self.invalid_comparison_msg(a, b)),
 			},
+			BitAnd | BitOr | BitXor | Shl | Shr => match (a, b) {
+				(Constant(ConstantData::Int(a)), Constant(ConstantData::Int(b))) => {
+					Ok(Constant(ConstantData::Int(match self {
+						BitAnd => a & b,
+						BitOr => a | b,
+						BitXor => a ^ b,
+						Shl => a.wrapping_shl(*b as u32),
+						_ => a.wrapping_shr(*b as u32),
+					})))
+				}
+				(a, b) if a.is_int() && b.is_int() => Ok(Int),
+				_ => Err(self.invalid_comparison_msg(a, b)),
+			},
+		}
+	}
+
+	/// A union can only be compared with null or a number, char or bool it could be holding
+	fn union_comparable(a: &CompType, b: &CompType) -> bool {
+		use CompType::*;
+		match (a, b) {
+			(Union(_), Union(_)) => false,
+			(Union(_), other) | (other, Union(_)) => {
+				matches!(other.widen(), Null | Int | Float | Char | Bool | Unknown)
+			}
+			_ => true,
+		}
+	}
+
+	/// Compares two constants with this comparison operator
+	fn compare<T: PartialOrd>(&self, a: T, b: T) -> bool {
+		match self {
+			Op::Lt => a < b,
+			Op::Gt => a > b,
+			Op::Le => a <= b,
+			_ => a >= b,
+		}
+	}
+
+	/// Chars can be compared, offset by an int or subtracted to get the distance between them
+	fn char_resulting_type(&self, a: &CompType, b: &CompType) -> Result<CompType, CompError> {
+		use CompType::*;
+		use Op::*;
+		match (self, a, b) {
+			(Lt | Gt | Le | Ge, Constant(ConstantData::Char(a)), Constant(ConstantData::Char(b))) => {
+				Ok(Constant(ConstantData::Bool(self.compare(a, b))))
+			}
+			(Lt | Gt | Le | Ge, a, b) if a.is_char() && b.is_char() => Ok(Bool),
+			(Add | Sub, Constant(ConstantData::Char(a)), Constant(ConstantData::Int(b))) => {
+				Ok(Constant(ConstantData::Char(if self == &Add {
+					a.wrapping_add(*b as u8)
+				} else {
+					a.wrapping_sub(*b as u8)
+				})))
+			}
+			(Add, Constant(ConstantData::Int(a)), Constant(ConstantData::Char(b))) => {
+				Ok(Constant(ConstantData::Char(b.wrapping_add(*a as u8))))
+			}
+			(Sub, Constant(ConstantData::Char(a)), Constant(ConstantData::Char(b))) => {
+				Ok(Constant(ConstantData::Int(*a as i32 - *b as i32)))
+			}
+			(Add | Sub, a, b) if a.is_char() && b.is_int() => Ok(Char),
+			(Add, a, b) if a.is_int() && b.is_char() => Ok(Char),
+			(Sub, a, b) if a.is_char() && b.is_char() => Ok(Int),
+			_ => Err(self.invalid_comparison_msg(a, b)),
 		}
 	}
 	fn invalid_comparison_msg(&self, a: &CompType, b: &CompType) -> CompError {
@@ -1320,28 +1727,60 @@ impl Display for Op {
 				Sub => "-",
 				Mult => "*",
 				Div => "/",
+				Mod => "%",
 				Eq => "==",
 				Neq => "!=",
-				Le => "<",
-				Ge => ">",
+				Lt => "<",
+				Gt => ">",
+				Le => "<=",
+				Ge => ">=",
+				And => "&&",
+				Or => "||",
+				BitAnd => "&",
+				BitOr => "|",
+				BitXor => "^",
+				Shl => "<<",
+				Shr => ">>",
 			}
 		)
 	}
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum Prefix {
-	Neg,
-}
-
 impl Prefix {
 	pub fn get_str(&self) -> &str {
 		match self {
 			Self::Neg => "-",
+			Self::Not => "!",
+			Self::BitNot => "~",
+		}
+	}
+
+	/// The type of applying this operator to a value, or None if it can't be applied
+	pub fn resulting_type(&self, ty: &CompType) -> Option<CompType> {
+		use CompType::*;
+		match (self, ty) {
+			// Operands that failed to type check have already been reported
+			(_, Unknown) => Some(Unknown),
+			(Self::Neg, Constant(ConstantData::Int(x))) => {
+				Some(Constant(ConstantData::Int(x.wrapping_neg())))
+			}
+			(Self::Neg, Constant(ConstantData::Float(x))) => Some(Constant(ConstantData::Float(-x))),
+			(Self::Neg, Int | Float) => Some(ty.clone()),
+			(Self::Not, Constant(ConstantData::Bool(x))) => Some(Constant(ConstantData::Bool(!x))),
+			(Self::Not, Bool) => Some(Bool),
+			(Self::BitNot, Constant(ConstantData::Int(x))) => Some(Constant(ConstantData::Int(!x))),
+			(Self::BitNot, Int) => Some(Int),
+			_ => None,
 		}
 	}
 }
 
+impl Display for Prefix {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.get_str())
+	}
+}
+
 impl ConstantData {
 	pub fn to_comp_data(self) -> CompData {
 		match self {
@@ -1349,6 +1788,7 @@ impl ConstantData {
 			Self::Float(val) => CompData::Float(val),
 			Self::Bool(val) => CompData::Bool(val),
 			Self::Str(val) => CompData::Str(val),
+			Self::Char(val) => CompData::Char(val),
 			Self::Null => CompData::Null,
 		}
 	}
@@ -1365,6 +1805,7 @@ impl ConstantData {
 			Int(_) => CompType::Int,
 			Float(_) => CompType::Float,
 			Bool(_) => CompType::Bool,
+			Char(_) => CompType::Char,
 			Null => CompType::Null,
 		}
 	}
@@ -1405,6 +1846,14 @@ pub fn transform_exp(
 			CompExpression::Array(map_vec!(elements, |x| transform_exp(x, env, scope, file)
 				.collect_errors_into(&mut errs)))
 		}
+		Template(parts) => {
+			CompExpression::Template(map_vec!(parts, |x| transform_exp(x, env, scope, file)
+				.collect_errors_into(&mut errs)))
+		}
+		DotAccess(..) if get_variant(exp, scope).is_some() => {
+			let (ty, variant) = get_variant(exp, scope).expect("Checked by the guard");
+			transform_variant(ty, variant, Vec::new(), loc.clone(), env, &mut errs)
+		}
 		DotAccess(val, key) => CompExpression::DotAccess(
 			transform_exp(val, env, scope, file).collect_errors_into(&mut errs),
 			key.clone(),
@@ -1420,10 +1869,113 @@ pub fn transform_exp(
 			transform_exp(arr, env, scope, file).collect_errors_into(&mut errs),
 			transform_exp(index, env, scope, file).collect_errors_into(&mut errs),
 		),
-		TypeDeclaration(_, _, _) => CompExpression::List(Vec::new()),
-		InitAssign(_, _, name, _, exp) => {
+		TypeDeclaration(..) => CompExpression::List(Vec::new()),
+		EnumDeclaration(_, variants, _) => {
+			for ((variant, variant_loc), payload) in variants {
+				transform_type(payload, scope, variant_loc.clone()).collect_errors_into(&mut errs);
+				if variants.iter().filter(|x| &x.0 .0 == variant).count() > 1 {
+					errs.push(CompError::RedeclareInSameScope(
+						variant.clone(),
+						None,
+						variant_loc.clone(),
+					));
+				}
+			}
+			CompExpression::List(Vec::new())
+		}
+		Match(subject, arms) => {
+			let subject = transform_exp(subject, env, scope, file).collect_errors_into(&mut errs);
+			let subject_loc = subject.located.clone();
+			let subject_ty = subject.result_type.widen();
+			let unguarded: Vec<_> = arms
+				.iter()
+				.filter(|arm| arm.guard.is_none())
+				.map(|arm| &arm.pattern)
+				.collect();
+			let missing = missing_cases(&subject_ty, &unguarded, scope);
+			if !missing.is_empty() {
+				errs.push(CompError::NonExhaustiveMatch(missing.join(", "), loc.clone()));
+			}
+			for arm_loc in unreachable_arms(&subject_ty, arms, scope) {
+				errs.push(CompError::UnreachablePattern(arm_loc));
+			}
+			let (subject, subject_assign) =
+				hidden_variable(format!(".match{}", loc.start), subject, env);
+
+			// Built from the last arm up, each arm is checked when the ones before it don't match
+			let mut otherwise = None;
+			for arm in arms.iter().rev() {
+				let mut scope = scope.create_child(Vec::new());
+				let mut bindings = Vec::new();
+				let current = read(subject.clone(), subject_loc.clone(), env);
+				let check = transform_pattern(
+					&arm.pattern,
+					current,
+					&mut scope,
+					env,
+					file,
+					&mut bindings,
+					&mut errs,
+				);
+				scope.resolve_scope(&arm.body, file);
+				// Guards can use the bindings so they're set first
+				let guard = arm.guard.as_ref().map(|guard| {
+					let guard =
+						transform_exp(guard, env, &mut scope, file).collect_errors_into(&mut errs);
+					let guard_ty = guard.result_type.clone();
+					let guard_loc = guard.located.clone();
+					if !guard_ty.is_bool() && guard_ty != CompType::Unknown {
+						errs.push(CompError::BoolInIf(guard_ty, guard_loc.clone()));
+					}
+					let guard = bindings.iter().cloned().chain([guard]).collect();
+					let guard = CompExpression::List(guard);
+					get_env(guard, env, guard_loc, Vec::new()).data
+				});
+				let check = match (check, guard) {
+					(Some(check), Some(guard)) => Some(and(check, guard, env)),
+					(check, guard) => check.or(guard),
+				};
+				let body =
+					transform_exp(&arm.body, env, &mut scope, file).collect_errors_into(&mut errs);
+				let body_loc = body.located.clone();
+				let then = CompExpression::List(bindings.into_iter().chain([body]).collect());
+				let then = get_env(then, env, body_loc, Vec::new()).data;
+				otherwise = Some(match check {
+					// The last arm of an exhaustive match has to match if it's reached
+					Some(cond) if otherwise.is_some() || !missing.is_empty() => {
+						let otherwise =
+							otherwise.unwrap_or_else(|| value(CompData::Null, loc.clone(), env));
+						let branch = CompExpression::IfElse(crate::ast2::IfElse {
+							cond,
+							then,
+							otherwise,
+						});
+						get_env(branch, env, loc.clone(), Vec::new()).data
+					}
+					// Arms after one that matches anything can't be reached
+					_ => then,
+				});
+			}
+			let result = otherwise.unwrap_or_else(|| value(CompData::Null, loc.clone(), env));
+			CompExpression::List(vec![subject_assign, result])
+		}
+		Impl((ty, _), generics, methods) => {
+			CompExpression::List(map_vec!(methods, |((name, name_loc), method, doc)| {
+				if !matches!(method.args.first(), Some(((arg, _), _)) if arg == "self") {
+					errs.push(CompError::MissingSelf(name.clone(), name_loc.clone()));
+				}
+				let func = Terminal(Symbol::Data(RawData::Func(method.as_method(ty, generics))));
+				let variable = (method_name(ty, name), name_loc.clone());
+				let func = Box::new((loc.clone(), func));
+				let declaration = InitAssign(false, true, variable, None, func, doc.clone());
+				transform_exp(&(loc.clone(), declaration), env, scope, file)
+					.collect_errors_into(&mut errs)
+			}))
+		}
+		InitAssign(_, _, name, _, exp, _) => {
 			if scope.variable_initialised(&name.0) {
-				errs.push(CompError::RedeclareInSameScope(name.0.clone(), loc.clone()));
+				let first = scope.get_variable(&name.0).ok().and_then(|x| x.declared_at);
+				errs.push(CompError::RedeclareInSameScope(name.0.clone(), first, loc.clone()));
 			}
 
 			let exp = transform_exp(exp, env, scope, file).collect_errors_into(&mut errs);
@@ -1445,6 +1997,7 @@ pub fn transform_exp(
 					external: false,
 					declared_at: None,
 					initialised: false,
+					doc: None,
 				}
 			};
 
@@ -1457,7 +2010,15 @@ pub fn transform_exp(
 		Assign(name, exp) => {
 			let lhs = resolve_memory_location!(name, env, scope);
 			let exp = transform_exp(exp, env, scope, file).collect_errors_into(&mut errs);
-			if let Some(lhs) = lhs {
+			if let Some(lhs) = &lhs
+				&& let Some(err) = const_reassign(lhs, &name.0)
+			{
+				errs.push(err);
+				CompExpression::List(Vec::new())
+			} else if let Some(lhs) = lhs {
+				if lhs.accessing.is_empty() {
+					scope.forget_narrowing(&lhs.variable.name);
+				}
 				CompExpression::Assign(lhs, exp)
 			} else {
 				errs.push(CompError::InvalidLeftHandForAssignment(
@@ -1467,10 +2028,84 @@ pub fn transform_exp(
 				CompExpression::List(Vec::new())
 			}
 		}
+		CompoundAssign(op, target, value, postfix) => {
+			let mem = resolve_memory_location!(target, env, scope);
+			let value = transform_exp(value, env, scope, file).collect_errors_into(&mut errs);
+			if let Some(mem) = &mem
+				&& let Some(err) = const_reassign(mem, &target.0)
+			{
+				errs.push(err);
+				CompExpression::List(Vec::new())
+			} else if let Some(mut mem) = mem {
+				if mem.accessing.is_empty() {
+					scope.forget_narrowing(&mem.variable.name);
+				}
+				let mut list = Vec::new();
+				// Indices are stored first so they're only evaluated once
+				for (i, (access, _)) in mem.accessing.iter_mut().enumerate() {
+					if let IndexOption::Index(index) = access
+						&& !matches!(index.expression.as_ref(), CompExpression::Value(_))
+					{
+						let name = format!(".index{}_{}", loc.start, i);
+						let (var, assign) = hidden_variable(name, index.clone(), env);
+						list.push(assign);
+						*index = read(var, index.located.clone(), env);
+					}
+				}
+				let mut current = read(mem.variable.clone(), target.0.clone(), env);
+				for (access, _) in &mem.accessing {
+					let exp = match access {
+						IndexOption::Index(index) => CompExpression::Index(current, index.clone()),
+						IndexOption::Dot(prop) => {
+							CompExpression::DotAccess(current, (prop.clone(), target.0.clone()))
+						}
+					};
+					// Any problems with the target are reported by the assignment
+					current = get_env(exp, env, target.0.clone(), Vec::new()).data;
+				}
+				let old = if *postfix {
+					let name = format!(".old{}", loc.start);
+					let (var, assign) = hidden_variable(name, current.clone(), env);
+					list.push(assign);
+					current = read(var.clone(), target.0.clone(), env);
+					Some(var)
+				} else {
+					None
+				};
+				// The target's value isn't known here, so the result can't be folded to a constant
+				current.result_type = current.result_type.widen();
+				let new = get_env(
+					CompExpression::BinOp(op.clone(), current, value),
+					env,
+					loc.clone(),
+					Vec::new(),
+				)
+				.collect_errors_into(&mut errs);
+				list.push(
+					get_env(CompExpression::Assign(mem, new), env, loc.clone(), Vec::new())
+						.collect_errors_into(&mut errs),
+				);
+				if let Some(old) = old {
+					list.push(read(old, loc.clone(), env));
+				}
+				CompExpression::List(list)
+			} else {
+				errs.push(CompError::InvalidLeftHandForAssignment(
+					target.1.clone(),
+					target.0.clone(),
+				));
+				CompExpression::List(Vec::new())
+			}
+		}
 		IfElse(cond, left, right) => {
 			let cond = transform_exp(cond, env, scope, file).collect_errors_into(&mut errs);
-			let then = transform_exp(left, env, scope, file).collect_errors_into(&mut errs);
-			let otherwise = transform_exp(right, env, scope, file).collect_errors_into(&mut errs);
+			let (when_true, when_false) = get_narrowing(&cond);
+			let then = scope.with_narrowing(when_true, |scope| {
+				transform_exp(left, env, scope, file).collect_errors_into(&mut errs)
+			});
+			let otherwise = scope.with_narrowing(when_false, |scope| {
+				transform_exp(right, env, scope, file).collect_errors_into(&mut errs)
+			});
 			CompExpression::IfElse(crate::ast2::IfElse {
 				cond,
 				then,
@@ -1478,47 +2113,211 @@ pub fn transform_exp(
 			})
 		}
 		Expression::Loop(exp, body) => {
+			forget_loop_narrowing(scope, &[exp, body]);
 			let cond = transform_exp(exp, env, scope, file).collect_errors_into(&mut errs);
-			let body = transform_exp(body, env, scope, file).collect_errors_into(&mut errs);
+			// Narrowing from a guard that breaks out of the body only holds inside the loop
+			let mut scope = scope.create_child(Vec::new());
+			scope.resolve_scope(body, file);
+			scope.set_in_loop(true);
+			let body = transform_exp(body, env, &mut scope, file).collect_errors_into(&mut errs);
 			CompExpression::WhileLoop { cond, body }
 		}
+		ForLoop(init, cond, step, body) => {
+			let mut scope = scope.create_child(Vec::new());
+			scope.resolve_scope(init, file).resolve_scope(body, file);
+			let init = transform_exp(init, env, &mut scope, file).collect_errors_into(&mut errs);
+			forget_loop_narrowing(&mut scope, &[cond, step, body]);
+			let cond = transform_exp(cond, env, &mut scope, file).collect_errors_into(&mut errs);
+			let step = transform_exp(step, env, &mut scope, file).collect_errors_into(&mut errs);
+			scope.set_in_loop(true);
+			let body = transform_exp(body, env, &mut scope, file).collect_errors_into(&mut errs);
+
+			// The step goes before the condition on every check but the first so `continue` runs it
+			let name = format!(".started{}", loc.start);
+			let not_started = value(CompData::Bool(false), loc.clone(), env);
+			let (started, start) = hidden_variable(name, not_started, env);
+			let step = CompExpression::IfElse(crate::ast2::IfElse {
+				cond: read(started.clone(), step.located.clone(), env),
+				then: step,
+				otherwise: assign(started, value(CompData::Bool(true), loc.clone(), env), env),
+			});
+			let step = get_env(step, env, loc.clone(), Vec::new()).data;
+			let cond_loc = cond.located.clone();
+			let cond = get_env(CompExpression::List(vec![step, cond]), env, cond_loc, Vec::new());
+			let body = CompExpression::WhileLoop {
+				cond: cond.data,
+				body,
+			};
+			let body = get_env(body, env, loc.clone(), Vec::new()).collect_errors_into(&mut errs);
+			CompExpression::List(vec![init, start, body])
+		}
+		ForOf(constant, name, iterable, body) => {
+			let mut scope = scope.create_child(Vec::new());
+			scope.add_variable(CompVariable {
+				name: name.0.clone(),
+				constant: *constant,
+				typing: CompType::Unknown,
+				initialised: false,
+				external: false,
+				declared_at: Some((file.to_string(), name.1.clone())),
+				doc: None,
+			});
+			scope.resolve_scope(body, file);
+
+			let iterable =
+				transform_exp(iterable, env, &mut scope, file).collect_errors_into(&mut errs);
+			let iter_ty = iterable.result_type.clone();
+			let iter_loc = iterable.located.clone();
+			if !iter_ty.is_array() && !iter_ty.is_str() && iter_ty != CompType::Unknown {
+				errs.push(CompError::CannotIterate(iter_ty, iter_loc.clone()));
+			}
+			let (iter, iter_assign) =
+				hidden_variable(format!(".iter{}", loc.start), iterable, env);
+			let zero = value(CompData::Int(0), loc.clone(), env);
+			let (index, index_assign) = hidden_variable(format!(".index{}", loc.start), zero, env);
+
+			// Problems with the iterable have already been reported
+			let length = CompExpression::DotAccess(
+				read(iter.clone(), iter_loc.clone(), env),
+				("length".to_string(), iter_loc.clone()),
+			);
+			let length = get_env(length, env, iter_loc.clone(), Vec::new()).data;
+			let current = read(index.clone(), iter_loc.clone(), env);
+			let cond = CompExpression::BinOp(Op::Lt, current.clone(), length);
+			let cond = get_env(cond, env, iter_loc.clone(), Vec::new()).data;
+			let element = CompExpression::Index(read(iter, iter_loc.clone(), env), current.clone());
+			let element = get_env(element, env, iter_loc.clone(), Vec::new()).data;
+
+			// The loop variable takes the type of the elements
+			scope.set_variable_type(&name.0, &element.result_type);
+			scope.set_variable_initialised(&name.0);
+			let var = scope.get_variable(&name.0).expect("The loop variable was just declared");
+			let set_element = assign(var, element, env);
+
+			// The index is moved on before the body so `continue` doesn't skip it
+			let one = value(CompData::Int(1), loc.clone(), env);
+			let next = CompExpression::BinOp(Op::Add, current, one);
+			let step = assign(index, get_env(next, env, loc.clone(), Vec::new()).data, env);
+
+			forget_loop_narrowing(&mut scope, &[body]);
+			scope.set_in_loop(true);
+			let body = transform_exp(body, env, &mut scope, file).collect_errors_into(&mut errs);
+			let body_loc = body.located.clone();
+			let body = CompExpression::List(vec![set_element, step, body]);
+			let body = CompExpression::WhileLoop {
+				cond,
+				body: get_env(body, env, body_loc, Vec::new()).data,
+			};
+			let body = get_env(body, env, loc.clone(), Vec::new()).data;
+			CompExpression::List(vec![iter_assign, index_assign, body])
+		}
+		Break => {
+			if !scope.in_loop() {
+				errs.push(CompError::OutsideOfLoop("break".to_string(), loc.clone()));
+			}
+			CompExpression::Break
+		}
+		Continue => {
+			if !scope.in_loop() {
+				errs.push(CompError::OutsideOfLoop("continue".to_string(), loc.clone()));
+			}
+			CompExpression::Continue
+		}
+		Return(value) => {
+			let value = match value {
+				Some(value) => transform_exp(value, env, scope, file).collect_errors_into(&mut errs),
+				None => get_env(CompExpression::List(Vec::new()), env, loc.clone(), Vec::new()).data,
+			};
+			match scope.get_return_type() {
+				Some(ty) if !ty.super_type_of(&value.result_type) => {
+					errs.push(CompError::InvalidReturn(
+						value.result_type.clone(),
+						ty,
+						value.located.clone(),
+					))
+				}
+				Some(_) => {}
+				None => errs.push(CompError::ReturnOutsideFunction(loc.clone())),
+			}
+			CompExpression::Return(value)
+		}
 		Expression::Block(expressions) => {
 			let mut env = env.clone();
 			let mut oks = Vec::new();
 			for exp in expressions {
 				env = transform_exp(exp, &env, scope, file).collect_errors_into(&mut errs);
+				// After an if that always leaves early the rest of the block knows its condition
+				if let CompExpression::IfElse(if_exp) = env.expression.as_ref() {
+					let (when_true, when_false) = get_narrowing(&if_exp.cond);
+					if if_exp.then.result_type == NEVER {
+						scope.narrow(when_false);
+					} else if if_exp.otherwise.result_type == NEVER {
+						scope.narrow(when_true);
+					}
+				}
 				oks.push(env.clone());
 			}
 			CompExpression::List(oks)
 		}
 		Expression::BinOp(op, left, right) => {
 			let left = transform_exp(left, env, scope, file).collect_errors_into(&mut errs);
-			let right = transform_exp(right, env, scope, file).collect_errors_into(&mut errs);
+			// The right side of `&&` and `||` only runs when the left side didn't decide the result
+			let narrowing = match (op, get_narrowing(&left)) {
+				(Op::And, (when_true, _)) => when_true,
+				(Op::Or, (_, when_false)) => when_false,
+				_ => Vec::new(),
+			};
+			let right = scope.with_narrowing(narrowing, |scope| {
+				transform_exp(right, env, scope, file).collect_errors_into(&mut errs)
+			});
 			CompExpression::BinOp(op.clone(), left, right)
 		}
-		Expression::FuncCall(name, generics, arguments) => {
+		Expression::OneOp(op, exp) => CompExpression::OneOp(
+			op.clone(),
+			transform_exp(exp, env, scope, file).collect_errors_into(&mut errs),
+		),
+		Expression::FuncCall(callee, _, arguments) if get_variant(&callee.1, scope).is_some() => {
+			let (ty, variant) = get_variant(&callee.1, scope).expect("Checked by the guard");
+			let args = map_vec!(arguments, |x| transform_exp(x, env, scope, file)
+				.collect_errors_into(&mut errs));
+			transform_variant(ty, variant, args, loc.clone(), env, &mut errs)
+		}
+		Expression::FuncCall(callee, generics, arguments) => {
+			let mut generics = map_vec!(generics, |x| {
+				transform_type(x, scope, loc.clone()).collect_errors_into(&mut errs)
+			});
 			let mut args = Vec::new();
-			let mut env = env.clone();
+			let func = match &callee.1 {
+				DotAccess(obj, (prop, prop_loc)) => {
+					let obj = transform_exp(obj, env, scope, file).collect_errors_into(&mut errs);
+					let is_field = matches!(
+						&obj.result_type,
+						CompType::Struct(fields) if fields.contains_key(prop)
+					);
+					match scope.find_method(prop, &obj.result_type) {
+						// Methods are plain functions taking the receiver as their first argument
+						Some((method, method_generics)) if !is_field => {
+							generics = method_generics;
+							let method = CompExpression::Read(method);
+							let method = get_env(method, &obj, prop_loc.clone(), Vec::new());
+							args.push(obj);
+							method.collect_errors_into(&mut errs)
+						}
+						_ => {
+							let access =
+								CompExpression::DotAccess(obj, (prop.clone(), prop_loc.clone()));
+							get_env(access, env, callee.0.clone(), Vec::new())
+								.collect_errors_into(&mut errs)
+						}
+					}
+				}
+				_ => transform_exp(callee, env, scope, file).collect_errors_into(&mut errs),
+			};
+			let mut env = args.last().unwrap_or(&func).clone();
 			for exp in arguments {
 				env = transform_exp(exp, &env, scope, file).collect_errors_into(&mut errs);
 				args.push(env.clone());
 			}
-			let func = if let Ok(var) = scope.get_variable(name) {
-				var
-			} else {
-				errs.push(CompError::CannotFindVariable(name.clone(), loc.clone()));
-				CompVariable {
-					name: name.clone(),
-					constant: false,
-					external: false,
-					typing: CompType::Unknown,
-					declared_at: None,
-					initialised: false,
-				}
-			};
-			let generics = map_vec!(generics, |x| {
-				transform_type(x, scope, loc.clone()).collect_errors_into(&mut errs)
-			});
 			CompExpression::Call(func, generics, args)
 		}
 		Expression::Terminal(sym) => match sym {
@@ -1534,15 +2333,33 @@ pub fn transform_exp(
 						typing: CompType::Unknown,
 						declared_at: None,
 						initialised: false,
+						doc: None,
 					}
 				};
-				CompExpression::Read(var)
+				match scope.get_narrowed(name) {
+					// Narrowed variables are read as their union and then converted
+					Some(ty) => {
+						let read = get_env(CompExpression::Read(var), env, loc.clone(), Vec::new());
+						CompExpression::Conversion(read.collect_errors_into(&mut errs), ty)
+					}
+					None => CompExpression::Read(var),
+				}
 			}
 			Symbol::Data(data) => {
 				CompExpression::Value(data.transform(scope, file).collect_errors_into(&mut errs))
 			}
 		},
-		Expression::Invalid => panic!("invalid {:?}", loc),
+		Expression::Invalid => {
+			// The parser has already reported this so it shouldn't cause any more errors
+			return WithErrors::new(
+				ExpEnvironment {
+					expression: Box::new(CompExpression::List(Vec::new())),
+					result_type: CompType::Unknown,
+					located: loc.clone(),
+				},
+				errs,
+			);
+		}
 	};
 	get_env(expression, env, loc.clone(), errs)
 }
@@ -1559,7 +2376,16 @@ fn transform_ast(ast: &SpannedExpression, scope: &mut Scope, file: &str) -> With
 	let functions = prog.body.get_functions();
 	for func in functions {
 		if let Some(prog2) = &func.body {
+			// Only the function's own variables can be captured, globals are fine to use
+			let locals = prog2.scope.get_variables();
 			let variables = prog2.body.get_all_mentioned_variables();
+			let variables: Vec<_> = variables
+				.into_iter()
+				.filter(|x| {
+					locals.contains_key(&x.get_name())
+						|| func.arguments.iter().any(|arg| arg.get_name() == x.get_name())
+				})
+				.collect();
 			let functions = prog2.body.get_functions();
 			for sub_func in functions {
 				if let Some(sub_prog) = &sub_func.body {
@@ -1662,6 +2488,21 @@ pub fn transform_type(
 }
 
 impl Function {
+	/// Turns a method into a plain function with `self` typed as what the method is for
+	fn as_method(&self, ty: &str, generics: &[(String, Option<CustomType>)]) -> Function {
+		let mut func = self.clone();
+		func.generics = generics.iter().chain(&self.generics).cloned().collect();
+		if let Some(((name, _), self_type @ None)) = func.args.first_mut() && name == "self" {
+			*self_type = Some(CustomType::Lone(UseType {
+				name: ty.to_string(),
+				generics: map_vec!(generics, |(name, _)| CustomType::Lone(UseType::simple(
+					name.clone()
+				))),
+			}));
+		}
+		func
+	}
+
 	fn transform_function(&self, scope: &mut Scope, file: &str) -> WithErrors<FunctionAst> {
 		if self.generics
 			== vec![(
@@ -1692,11 +2533,12 @@ impl Function {
 			};
 			arguments.push(CompVariable {
 				name: arg.0 .0.clone(),
-				constant: true,
+				constant: false,
 				typing: arg_ty,
 				external: false,
 				declared_at: Some((file.to_string(), arg.0 .1.clone())),
 				initialised: true,
+				doc: None,
 			});
 		}
 		let return_type = transform_type(&self.return_type.0, scope, self.return_type.1.clone())
@@ -1706,6 +2548,7 @@ impl Function {
 			Some(body) => {
 				let mut local_scope = scope.create_child(arguments.clone());
 				let local_scope = local_scope.resolve_scope(body.as_ref(), file);
+				local_scope.set_return_type(&return_type);
 				let body =
 					transform_ast(body.as_ref(), local_scope, file).collect_errors_into(&mut errs);
 				FunctionAst {
@@ -1746,11 +2589,12 @@ impl Function {
 			};
 			arguments.push(CompVariable {
 				name: arg.0 .0.clone(),
-				constant: true,
+				constant: false,
 				typing: arg_ty,
 				external: false,
 				declared_at: Some((file.to_string(), arg.0 .1.clone())),
 				initialised: true,
+				doc: None,
 			});
 		}
 		let return_type = transform_type(
@@ -1782,23 +2626,25 @@ impl Function {
 				};
 				arguments.push(CompVariable {
 					name: arg.0 .0.clone(),
-					constant: true,
+					constant: false,
 					typing: arg_ty,
 					external: false,
 					declared_at: Some((file.to_string(), arg.0 .1.clone())),
 					initialised: true,
+					doc: None,
 				});
 			}
 			let return_type =
-				transform_type(&self.return_type.0, scope, self.return_type.1.clone())
+				transform_type(&self.return_type.0, &generic_scope, self.return_type.1.clone())
 					.collect_errors_into(&mut errs);
 			(arguments, return_type)
 		};
 
 		let func = match &self.body {
 			Some(body) => {
-				let mut local_scope = scope.create_child(arguments.clone());
+				let mut local_scope = local_scope.create_child(arguments.clone());
 				let local_scope = local_scope.resolve_scope(body.as_ref(), file);
+				local_scope.set_return_type(&return_type);
 				let body =
 					transform_ast(body.as_ref(), local_scope, file).collect_errors_into(&mut errs);
 				FunctionAst {
@@ -1830,6 +2676,20 @@ pub fn resolve_type(
 	let mut errs: Vec<CompError> = Vec::new();
 	let ty = match exp {
 		Conversion(_exp, ty) => ty.clone(),
+		Template(parts) => {
+			let mut len = 0;
+			for part in parts.iter() {
+				match part.result_type.max_text_len() {
+					Some(part_len) => len += part_len,
+					None if part.result_type == CompType::Unknown => {}
+					None => errs.push(CompError::CannotInterpolate(
+						part.result_type.clone(),
+						part.located.clone(),
+					)),
+				}
+			}
+			CompType::Str(ConstantData::Int(len).to_type().boxed())
+		}
 		DotAccess(val, (key, _)) => {
 			if let CompType::Union(types) = &val.result_type {
 				let union = get_shared_access_types(types, key);
@@ -1856,8 +2716,11 @@ pub fn resolve_type(
 					CompType::Unknown
 				};
 				result_type
-			} else if let CompType::Array(_, len) = &val.result_type {
-				let result_type = if key == "length" {
+			} else if let CompType::Array(_, len) | CompType::Str(len) = &val.result_type {
+				let result_type = if key == "length" && val.result_type.is_str() {
+					// A string's type only knows the most characters it can hold, like a template's
+					CompType::Int
+				} else if key == "length" {
 					len.as_ref().clone()
 				} else {
 					errs.push(CompError::PropertyDoesNotExistOnType(
@@ -1896,10 +2759,20 @@ pub fn resolve_type(
 		}
 
 		Typeof(_) => CompType::Type,
+		// Anything after an expression that never finishes can't be reached
+		List(exps) if exps.iter().any(|x| x.result_type == NEVER) => NEVER,
 		List(exps) => exps
 			.last()
 			.map(|x| x.result_type.clone())
 			.unwrap_or(CompType::Null),
+		Break | Continue | Return(_) => NEVER,
+		Variant(ty, _, _) => ty.clone(),
+		Tag(_) => CompType::Int,
+		Is(_, _) => CompType::Bool,
+		Payload(exp, tag) => match &exp.result_type {
+			CompType::Enum(_, variants) => variants[*tag].1.clone(),
+			_ => CompType::Unknown,
+		},
 		WhileLoop { cond, body } => {
 			if !cond.result_type.is_bool() {
 				errs.push(CompError::BoolInWhile(
@@ -1918,7 +2791,7 @@ pub fn resolve_type(
 			}
 			let then_ty = if_exp.then.result_type.clone();
 			let other_ty = if_exp.otherwise.result_type.clone();
-			CompType::Union(vec![then_ty, other_ty])
+			CompType::Union(vec![then_ty, other_ty]).flatten()
 		}
 		Value(data) => data.get_type(),
 		Index(arr, i) => {
@@ -2001,13 +2874,22 @@ pub fn resolve_type(
 				errs.push(err);
 				CompType::Unknown
 			}),
-		OneOp(_, val) => val.result_type.clone(),
+		OneOp(op, val) => op.resulting_type(&val.result_type).unwrap_or_else(|| {
+			errs.push(CompError::InvalidPrefixOperation(
+				op.clone(),
+				val.result_type.clone(),
+				located,
+			));
+			CompType::Unknown
+		}),
 		Read(var) => var.get_type(),
-		Call(var, generics, args) => {
-			let result_type = if let CompType::Callible(arg_types, return_type) = var.get_type() {
+		Call(callee, generics, args) => {
+			let result_type = if let CompType::Callible(arg_types, return_type) =
+				callee.result_type.clone()
+			{
 				if arg_types.len() != args.len() {
 					errs.push(CompError::WrongArgumentsCount(
-						var.get_name(),
+						callee.get_name(),
 						args.len(),
 						arg_types.len(),
 						located.clone(),
@@ -2029,11 +2911,14 @@ pub fn resolve_type(
 				return_type
 					.substitute_generics(generics, located.clone())
 					.collect_errors_into(&mut errs)
+			} else if callee.result_type == CompType::Unknown {
+				// Whatever caused the callee to be unknown has already been reported
+				CompType::Unknown
 			} else {
 				errs.push(CompError::NonfunctionCall(
-					var.get_name(),
-					var.get_type(),
-					located,
+					callee.get_name(),
+					callee.result_type.clone(),
+					callee.located.clone(),
 				));
 				CompType::Unknown
 			};
@@ -2060,6 +2945,363 @@ pub fn get_env(
 	)
 }
 
+/// Gets the enum type and variant name for accesses like `Shape.Circle`
+/// A variable with the same name as the enum shadows it, so that's a normal property access
+fn get_variant<'a>(
+	exp: &'a Expression,
+	scope: &Scope,
+) -> Option<(CompType, &'a (String, Range<usize>))> {
+	if let Expression::DotAccess(val, variant) = exp
+		&& let Expression::Terminal(Symbol::Identifier(name)) = &val.1
+		&& !scope.variable_exists(name)
+	{
+		let ty = scope.get_type(name).ok()?;
+		matches!(ty, CompType::Enum(_, _)).then_some((ty, variant))
+	} else {
+		None
+	}
+}
+
+/// Creates a value of an enum, variants like `Circle(Float)` take their fields as arguments
+/// Variants with named fields take a struct and ones without a payload don't need to be called
+fn transform_variant(
+	ty: CompType,
+	(variant, variant_loc): &(String, Range<usize>),
+	args: Vec<ExpEnvironment>,
+	loc: Range<usize>,
+	env: &ExpEnvironment,
+	errs: &mut Vec<CompError>,
+) -> CompExpression {
+	let CompType::Enum(_, variants) = &ty else {
+		unreachable!("Only enums have variants")
+	};
+	let Some(tag) = variants.iter().position(|x| &x.0 == variant) else {
+		errs.push(CompError::UnknownVariant(variant.clone(), ty.clone(), variant_loc.clone()));
+		return CompExpression::List(args);
+	};
+	let fields = match &variants[tag].1 {
+		CompType::Struct(fields) => fields.clone(),
+		_ => BTreeMap::new(),
+	};
+	let named = !fields.is_empty() && !fields.contains_key("0");
+	let expected = if named { 1 } else { fields.len() };
+	if args.len() != expected {
+		errs.push(CompError::VariantFieldCount(
+			variant.clone(),
+			expected,
+			args.len(),
+			loc.clone(),
+		));
+	}
+	let payload = if named && let [arg] = &args[..] {
+		let arg = arg.clone();
+		let same_fields = match &arg.result_type {
+			CompType::Struct(arg_fields) => arg_fields.keys().eq(fields.keys()),
+			ty => ty == &CompType::Unknown,
+		};
+		if !same_fields || !variants[tag].1.super_type_of(&arg.result_type) {
+			errs.push(CompError::InvalidAssignment(
+				arg.result_type.clone(),
+				variants[tag].1.clone(),
+				arg.located.clone(),
+			));
+		}
+		arg
+	} else {
+		let mut payload = BTreeMap::new();
+		for (i, arg) in args.into_iter().enumerate() {
+			if let Some(field_ty) = fields.get(&i.to_string())
+				&& !field_ty.super_type_of(&arg.result_type)
+			{
+				errs.push(CompError::InvalidAssignment(
+					arg.result_type.clone(),
+					field_ty.clone(),
+					arg.located.clone(),
+				));
+			}
+			payload.insert(i.to_string(), (arg.located.clone(), arg));
+		}
+		get_env(CompExpression::Struct(payload), env, loc, Vec::new()).data
+	};
+	CompExpression::Variant(ty, tag, payload)
+}
+
+/// Builds the check for whether the subject matches a pattern, adding assignments for its bindings
+/// Patterns that match anything don't need a check
+fn transform_pattern(
+	(loc, pattern): &SpannedPattern,
+	subject: ExpEnvironment,
+	scope: &mut Scope,
+	env: &ExpEnvironment,
+	file: &str,
+	bindings: &mut Vec<ExpEnvironment>,
+	errs: &mut Vec<CompError>,
+) -> Option<ExpEnvironment> {
+	let subject_ty = subject.result_type.clone();
+	match pattern {
+		Pattern::Wildcard => None,
+		Pattern::Binding(name) => {
+			if let Some(first) = scope.get_variables().get(name) {
+				let first = first.declared_at.clone();
+				errs.push(CompError::RedeclareInSameScope(name.clone(), first, loc.clone()));
+			}
+			let var = CompVariable {
+				name: name.clone(),
+				constant: true,
+				typing: subject_ty,
+				external: false,
+				declared_at: Some((file.to_string(), loc.clone())),
+				initialised: true,
+				doc: None,
+			};
+			scope.add_variable(var.clone());
+			bindings.push(assign(var, subject, env));
+			None
+		}
+		Pattern::Literal(data) => {
+			let data = data.transform(scope, file).collect_errors_into(errs);
+			let literal = value(data, loc.clone(), env);
+			if !can_match(&subject_ty, &literal.result_type) {
+				errs.push(CompError::MismatchedPattern(
+					literal.result_type.clone(),
+					subject_ty,
+					loc.clone(),
+				));
+			}
+			let (is, subject) = narrow(subject, &literal.result_type.widen(), env);
+			let check = CompExpression::BinOp(Op::Eq, subject, literal);
+			let check = get_env(check, env, loc.clone(), Vec::new()).data;
+			Some(match is {
+				Some(is) => and(is, check, env),
+				None => check,
+			})
+		}
+		Pattern::Typed(name, ty) => {
+			let ty = transform_type(ty, scope, loc.clone()).collect_errors_into(errs);
+			if !can_match(&subject_ty, &ty) {
+				errs.push(CompError::MismatchedPattern(ty.clone(), subject_ty, loc.clone()));
+			}
+			let (is, subject) = narrow(subject, &ty, env);
+			if let Some(name) = name {
+				let binding = (loc.clone(), Pattern::Binding(name.clone()));
+				transform_pattern(&binding, subject, scope, env, file, bindings, errs);
+			}
+			is
+		}
+		Pattern::Variant(name, (variant, variant_loc), fields) => {
+			let ty = match scope.get_type(name) {
+				Ok(ty) => ty,
+				Err(_) => {
+					errs.push(CompError::CannotFindType(name.clone(), loc.clone()));
+					return None;
+				}
+			};
+			let tag = match &ty {
+				CompType::Enum(_, variants) => variants.iter().position(|x| &x.0 == variant),
+				_ => None,
+			};
+			let Some(tag) = tag else {
+				errs.push(CompError::UnknownVariant(variant.clone(), ty, variant_loc.clone()));
+				return None;
+			};
+			if !can_match(&subject_ty, &ty) {
+				errs.push(CompError::MismatchedPattern(ty.clone(), subject_ty, loc.clone()));
+			}
+			let (is, subject) = narrow(subject, &ty, env);
+			let tag_value = value(CompData::Int(tag as i32), variant_loc.clone(), env);
+			let current_tag = CompExpression::Tag(subject.clone());
+			let current_tag = get_env(current_tag, env, loc.clone(), Vec::new()).data;
+			let check = CompExpression::BinOp(Op::Eq, current_tag, tag_value);
+			let check = get_env(check, env, loc.clone(), Vec::new()).data;
+			let mut check = match is {
+				Some(is) => and(is, check, env),
+				None => check,
+			};
+
+			let payload = CompExpression::Payload(subject, tag);
+			let payload = get_env(payload, env, loc.clone(), Vec::new()).data;
+			if let CompType::Struct(payload_fields) = &payload.result_type
+				&& payload_fields.contains_key("0")
+				&& payload_fields.len() != fields.len()
+			{
+				errs.push(CompError::VariantFieldCount(
+					variant.clone(),
+					payload_fields.len(),
+					fields.len(),
+					loc.clone(),
+				));
+			}
+			for (field, pattern) in fields {
+				let field_loc = pattern.0.clone();
+				let access = CompExpression::DotAccess(payload.clone(), (field.clone(), field_loc));
+				let field =
+					get_env(access, env, pattern.0.clone(), Vec::new()).collect_errors_into(errs);
+				if let Some(field_check) =
+					transform_pattern(pattern, field, scope, env, file, bindings, errs)
+				{
+					check = and(check, field_check, env);
+				}
+			}
+			Some(check)
+		}
+	}
+}
+
+/// Whether a pattern of some type could match any values of the subject's type
+fn can_match(subject: &CompType, pattern: &CompType) -> bool {
+	let pattern = pattern.widen();
+	subject == &CompType::Unknown
+		|| subject
+			.get_variants()
+			.iter()
+			.any(|x| pattern.super_type_of(x) || x.super_type_of(&pattern))
+}
+
+/// Narrows a union down to the variants a type covers
+/// Gives back the check for whether the value holds one of them, which isn't needed for other types
+fn narrow(
+	subject: ExpEnvironment,
+	ty: &CompType,
+	env: &ExpEnvironment,
+) -> (Option<ExpEnvironment>, ExpEnvironment) {
+	if !subject.result_type.is_union() || ty.super_type_of(&subject.result_type) {
+		return (None, subject);
+	}
+	let loc = subject.located.clone();
+	let is = CompExpression::Is(subject.clone(), ty.clone());
+	let is = get_env(is, env, loc, Vec::new()).data;
+	let variants = subject.result_type.get_variants();
+	let narrowed = variants.into_iter().filter(|x| ty.super_type_of(x)).collect();
+	let subject = ExpEnvironment {
+		result_type: CompType::Union(narrowed).flatten(),
+		..subject
+	};
+	(Some(is), subject)
+}
+
+/// The cases a match doesn't handle with the given patterns, described for showing in an error
+/// Each pattern takes away the part of the type it always matches and whatever's left is missing
+fn missing_cases(ty: &CompType, patterns: &[&SpannedPattern], scope: &Scope) -> Vec<String> {
+	let matches_anything =
+		|pattern: &Pattern| matches!(pattern, Pattern::Wildcard | Pattern::Binding(_));
+	let remaining = patterns.iter().fold(ty.clone(), |remaining, (loc, pattern)| match pattern {
+		Pattern::Wildcard | Pattern::Binding(_) => NEVER,
+		Pattern::Typed(_, covered) => {
+			// Errors in the type are reported when the pattern itself is checked
+			let covered = transform_type(covered, scope, loc.clone()).data;
+			calc_type_from_not(&remaining, &covered)
+		}
+		Pattern::Literal(RawData::Null) => calc_type_from_not(&remaining, &CompType::Null),
+		_ => remaining,
+	});
+	let handles = |case: &dyn Fn(&Pattern) -> bool| patterns.iter().any(|x| case(&x.1));
+	let missing_from = |variant: &CompType| match variant {
+		CompType::Enum(name, variants) => variants
+			.iter()
+			.filter(|(variant, _)| {
+				!handles(&|pattern| {
+					matches!(pattern, Pattern::Variant(ty, (x, _), fields)
+						if ty == name && x == variant
+							&& fields.iter().all(|x| matches_anything(&x.1 .1)))
+				})
+			})
+			.map(|(variant, _)| format!("'{}.{}'", name, variant))
+			.collect(),
+		CompType::Bool => [true, false]
+			.into_iter()
+			.filter(|x| !handles(&|pattern| pattern == &Pattern::Literal(RawData::Bool(*x))))
+			.map(|x| format!("'{}'", x))
+			.collect(),
+		CompType::Unknown => Vec::new(),
+		// Values of types like Int can't all be listed so only a catch-all handles them
+		variant if !ty.is_union() => {
+			vec![format!("every '{}', add a '_' arm for the rest", variant)]
+		}
+		variant => vec![format!("'{}'", variant)],
+	};
+	remaining.get_variants().iter().flat_map(missing_from).collect()
+}
+
+/// Finds arms that can't be reached because the ones before them always match first
+fn unreachable_arms(ty: &CompType, arms: &[MatchArm], scope: &Scope) -> Vec<Range<usize>> {
+	let everything = missing_cases(ty, &[], scope);
+	if everything.is_empty() {
+		return Vec::new();
+	}
+	let mut unreachable = Vec::new();
+	// Guarded arms might not match so they don't hide the arms after them
+	let mut before: Vec<&SpannedPattern> = Vec::new();
+	for arm in arms {
+		let missing = missing_cases(ty, &before, scope);
+		let not_matched = missing_cases(ty, &[&arm.pattern], scope);
+		let matched: Vec<_> = everything.iter().filter(|x| !not_matched.contains(x)).collect();
+		if missing.is_empty() || !matched.is_empty() && matched.iter().all(|x| !missing.contains(x))
+		{
+			unreachable.push(arm.pattern.0.clone());
+		}
+		if arm.guard.is_none() {
+			before.push(&arm.pattern);
+		}
+	}
+	unreachable
+}
+
+fn and(left: ExpEnvironment, right: ExpEnvironment, env: &ExpEnvironment) -> ExpEnvironment {
+	let located = left.located.start..right.located.end;
+	get_env(CompExpression::BinOp(Op::And, left, right), env, located, Vec::new()).data
+}
+
+/// The hidden variable a method is stored in, `.` keeps it from clashing with anything declared
+fn method_name(ty: &str, method: &str) -> String {
+	format!("{}.{}", ty, method)
+}
+
+/// Stores an expression in a variable hidden from the program, returning it and the assignment
+fn hidden_variable(
+	name: String,
+	exp: ExpEnvironment,
+	env: &ExpEnvironment,
+) -> (CompVariable, ExpEnvironment) {
+	let var = CompVariable {
+		name,
+		typing: exp.result_type.widen(),
+		constant: false,
+		external: false,
+		declared_at: None,
+		initialised: true,
+		doc: None,
+	};
+	(var.clone(), assign(var, exp, env))
+}
+
+fn assign(variable: CompVariable, exp: ExpEnvironment, env: &ExpEnvironment) -> ExpEnvironment {
+	let located = exp.located.clone();
+	let mem = MemoryLocation {
+		variable,
+		accessing: Vec::new(),
+	};
+	get_env(CompExpression::Assign(mem, exp), env, located, Vec::new()).data
+}
+
+/// Constants can't be given a new value, though the fields and elements inside them can change
+fn const_reassign(mem: &MemoryLocation, loc: &Range<usize>) -> Option<CompError> {
+	(mem.accessing.is_empty() && mem.variable.is_const()).then(|| {
+		CompError::ConstReassign(
+			mem.variable.get_name(),
+			mem.variable.get_declaration_location(),
+			loc.clone(),
+		)
+	})
+}
+
+fn value(data: CompData, located: Range<usize>, env: &ExpEnvironment) -> ExpEnvironment {
+	get_env(CompExpression::Value(data), env, located, Vec::new()).data
+}
+
+fn read(var: CompVariable, located: Range<usize>, env: &ExpEnvironment) -> ExpEnvironment {
+	get_env(CompExpression::Read(var), env, located, Vec::new()).data
+}
+
 fn get_env_from_scope(_scope: &Scope) -> ExpEnvironment {
 	ExpEnvironment {
 		result_type: CompType::Null,
@@ -2078,23 +3320,168 @@ pub fn get_shared_access_types(types: &[CompType], key: &str) -> Vec<CompType> {
 		.collect::<Vec<_>>()
 }
 
+/// Collects the names of variables assigned to anywhere in an expression, including in functions
+fn assigned_variables((_, exp): &SpannedExpression, names: &mut Vec<String>) {
+	use Expression::*;
+	match exp {
+		Assign(target, value) | CompoundAssign(_, target, value, _) => {
+			if let Terminal(Symbol::Identifier(name)) = &target.1 {
+				names.push(name.clone());
+			}
+			assigned_variables(target, names);
+			assigned_variables(value, names);
+		}
+		DotAccess(exp, _) | Typeof(exp) | Conversion(exp, _) | OneOp(_, exp) => {
+			assigned_variables(exp, names)
+		}
+		InitAssign(_, _, _, _, exp, _) | Return(Some(exp)) => assigned_variables(exp, names),
+		BinOp(_, a, b) | Index(a, b) | Loop(a, b) | ForOf(_, _, a, b) => {
+			assigned_variables(a, names);
+			assigned_variables(b, names);
+		}
+		IfElse(cond, then, otherwise) => {
+			for exp in [cond, then, otherwise] {
+				assigned_variables(exp, names);
+			}
+		}
+		ForLoop(init, cond, step, body) => {
+			for exp in [init, cond, step, body] {
+				assigned_variables(exp, names);
+			}
+		}
+		Array(exps) | Block(exps) | Template(exps) => {
+			for exp in exps {
+				assigned_variables(exp, names);
+			}
+		}
+		FuncCall(func, _, args) => {
+			assigned_variables(func, names);
+			for arg in args {
+				assigned_variables(arg, names);
+			}
+		}
+		Struct(fields) => {
+			for (_, exp) in fields {
+				assigned_variables(exp, names);
+			}
+		}
+		Match(subject, arms) => {
+			assigned_variables(subject, names);
+			for arm in arms {
+				if let Some(guard) = &arm.guard {
+					assigned_variables(guard, names);
+				}
+				assigned_variables(&arm.body, names);
+			}
+		}
+		Terminal(Symbol::Data(RawData::Func(func))) => {
+			if let Some(body) = &func.body {
+				assigned_variables(body, names);
+			}
+		}
+		Impl(_, _, methods) => {
+			for (_, method, _) in methods {
+				if let Some(body) = &method.body {
+					assigned_variables(body, names);
+				}
+			}
+		}
+		Terminal(_) | TypeDeclaration(..) | EnumDeclaration(..) | Return(None) | Break
+		| Continue | Invalid => {}
+	}
+}
+
+/// A loop can run again after its body assigns to a variable, so nothing it assigns stays narrowed
+fn forget_loop_narrowing(scope: &mut Scope, parts: &[&SpannedExpression]) {
+	let mut names = Vec::new();
+	for part in parts {
+		assigned_variables(part, &mut names);
+	}
+	for name in names {
+		scope.forget_narrowing(&name);
+	}
+}
+
+/// Variables with the types they've been narrowed to
+pub type Narrowing = Vec<(String, CompType)>;
+
+/// The types variables are narrowed to when a condition is true and when it's false
+fn get_narrowing(cond: &ExpEnvironment) -> (Narrowing, Narrowing) {
+	use CompExpression::*;
+	match cond.expression.as_ref() {
+		OneOp(Prefix::Not, exp) => {
+			let (when_true, when_false) = get_narrowing(exp);
+			(when_false, when_true)
+		}
+		// Both sides are true for `&&` to be true but either one could be why it's false
+		BinOp(Op::And, a, b) => {
+			let mut when_true = get_narrowing(a).0;
+			when_true.append(&mut get_narrowing(b).0);
+			(when_true, Vec::new())
+		}
+		BinOp(Op::Or, a, b) => {
+			let mut when_false = get_narrowing(a).1;
+			when_false.append(&mut get_narrowing(b).1);
+			(Vec::new(), when_false)
+		}
+		BinOp(op @ (Op::Eq | Op::Neq), a, b) => {
+			let Some((name, current, compared)) =
+				get_compared_variable(a, b).or_else(|| get_compared_variable(b, a))
+			else {
+				return (Vec::new(), Vec::new());
+			};
+			let variants = current.get_variants();
+			let matching = variants.into_iter().filter(|x| compared.super_type_of(x)).collect();
+			let matching = vec![(name.clone(), CompType::Union(matching).flatten())];
+			let rest = vec![(name, calc_type_from_not(&current, &compared))];
+			if op == &Op::Eq {
+				(matching, rest)
+			} else {
+				(rest, matching)
+			}
+		}
+		_ => (Vec::new(), Vec::new()),
+	}
+}
+
+/// A union variable being checked with `typeof x == typeof y` or `x == null`
+/// Gives back its name, its current type and the type it's compared against
+fn get_compared_variable(
+	exp: &ExpEnvironment,
+	other: &ExpEnvironment,
+) -> Option<(String, CompType, CompType)> {
+	use CompExpression::*;
+	let (exp, compared) = match (exp.expression.as_ref(), other.expression.as_ref()) {
+		(Typeof(exp), Typeof(other)) => (exp, other.result_type.widen()),
+		(_, Value(CompData::Null)) => (exp, CompType::Null),
+		_ => return None,
+	};
+	// When the other side could be one of several types, their types differing doesn't say which
+	// variant this one holds
+	if compared.is_union() || compared == CompType::Unknown {
+		return None;
+	}
+	let name = match exp.expression.as_ref() {
+		Read(var) => var.name.clone(),
+		Conversion(read, _) => match read.expression.as_ref() {
+			Read(var) => var.name.clone(),
+			_ => return None,
+		},
+		_ => return None,
+	};
+	let current = exp.result_type.clone();
+	current.is_union().then_some((name, current, compared))
+}
+
+/// The part of a type left over once everything that is also of type `not` is taken away
 fn calc_type_from_not(from: &CompType, not: &CompType) -> CompType {
 	use CompType::*;
 	if let Union(types) = from {
-		if types.contains(not) {
-			Union(types.iter().filter(|ty| ty != &not).cloned().collect()).flatten()
-		} else {
-			from.clone()
-		}
-	} else if not.is_primitive() && from.is_primitive() {
-		if not == from {
-			Union(Vec::new())
-		} else {
-			from.clone()
-		}
-	} else {
-		// ToDO: Actually match all cases, this should not be reached if correct code is written but still technically reachable and so needs to be handled properly
+		Union(types.iter().filter(|ty| !not.super_type_of(ty)).cloned().collect()).flatten()
+	} else if not.super_type_of(from) {
 		NEVER
+	} else {
+		from.clone()
 	}
 }
 
@@ -2105,6 +3492,7 @@ impl RawData {
 			RawData::Int(val) => CompData::Int(*val),
 			RawData::Float(val) => CompData::Float(*val),
 			RawData::Str(val) => CompData::Str(val.clone()),
+			RawData::Char(val) => CompData::Char(*val),
 			RawData::Bool(val) => CompData::Bool(*val),
 			RawData::Null => CompData::Null,
 			RawData::Func(func) => CompData::Func(
@@ -2128,6 +3516,7 @@ impl CompExpression {
 					.map(|x| x.replace_generics(generics))
 					.collect(),
 			),
+			Template(parts) => Template(map_vec!(parts, |x| x.replace_generics(generics))),
 			DotAccess(exp, property) => DotAccess(exp.replace_generics(generics), property.clone()),
 			// TODO: Work out how to keep track of generics through nested functions
 			// Currently it will take on multiple sets of generics but not distinguish between which set when accessing them by position
@@ -2149,8 +3538,8 @@ impl CompExpression {
 			// TODO: make generics work properly here
 			Read(var) => Read(var.clone()),
 			OneOp(op, exp) => OneOp(op.clone(), exp.replace_generics(generics)),
-			Call(var, passed_generics, args) => Call(
-				var.clone(),
+			Call(callee, passed_generics, args) => Call(
+				callee.replace_generics(generics),
 				map_vec!(passed_generics, |x| {
 					x.substitute_generics(generics, 0..0)
 						.expect(generics_err_msg)
@@ -2183,6 +3572,19 @@ impl CompExpression {
 				ty.substitute_generics(generics, 0..0)
 					.expect("Error in conversion"),
 			),
+			Break => Break,
+			Continue => Continue,
+			Return(exp) => Return(exp.replace_generics(generics)),
+			Variant(ty, tag, payload) => {
+				Variant(ty.clone(), *tag, payload.replace_generics(generics))
+			}
+			Tag(exp) => Tag(exp.replace_generics(generics)),
+			Is(exp, ty) => Is(
+				exp.replace_generics(generics),
+				ty.substitute_generics(generics, 0..0)
+					.expect("Error in type check"),
+			),
+			Payload(exp, tag) => Payload(exp.replace_generics(generics), *tag),
 		}
 	}
 }
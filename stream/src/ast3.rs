@@ -131,11 +131,16 @@ fn count_max_references_in_env(env: &ExpEnvironment, accesses: &mut Vec<Accesses
 		Typeof(exp) => count_max_references_in_env(exp, accesses),
 		Conversion(exp, _) => count_max_references_in_env(exp, accesses),
 		DotAccess(exp, _) => count_max_references_in_env(exp, accesses),
+		Return(exp) => count_max_references_in_env(exp, accesses),
+		Variant(_, _, exp) | Tag(exp) | Payload(exp, _) | Is(exp, _) => {
+			count_max_references_in_env(exp, accesses)
+		}
+		Break | Continue => {}
 		Index(arr, idx) => {
 			count_max_references_in_env(arr, accesses);
 			count_max_references_in_env(idx, accesses)
 		}
-		Array(envs) => {
+		Array(envs) | Template(envs) => {
 			for env in envs {
 				count_max_references_in_env(env, accesses)
 			}
@@ -146,15 +151,20 @@ fn count_max_references_in_env(env: &ExpEnvironment, accesses: &mut Vec<Accesses
 			}
 		}
 		Call(func, _, envs) => {
-			if let Some(access) = accesses.iter_mut().find(|access| &access.variable == func) {
-				access.read += 1;
+			// Calling a function by name only reads it, anything else is counted as normal
+			if let Read(func) = func.expression.as_ref() {
+				if let Some(access) = accesses.iter_mut().find(|access| &access.variable == func) {
+					access.read += 1;
+				} else {
+					accesses.push(Accesses {
+						variable: func.clone(),
+						read: 1,
+						write: 0,
+						capture: 0,
+					})
+				}
 			} else {
-				accesses.push(Accesses {
-					variable: func.clone(),
-					read: 1,
-					write: 0,
-					capture: 0,
-				})
+				count_max_references_in_env(func, accesses);
 			}
 			for env in envs {
 				count_max_references_in_env(env, accesses)
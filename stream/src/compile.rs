@@ -1,15 +1,16 @@
-use crate::ast1::{ConstantData, Op};
+use crate::ast1::{ConstantData, Op, Prefix};
 use crate::ast3::*;
-use crate::settings::Settings;
+use crate::runner::ImportMap;
+use crate::settings::{Emit, OptLevel, Settings};
 use crate::{ast2::*, map_vec};
-use fxhash::hash32;
+use inkwell::basic_block::BasicBlock;
 use inkwell::builder::Builder;
 use inkwell::context::Context;
 use inkwell::debug_info::{AsDIScope, DICompileUnit, DebugInfoBuilder};
 use inkwell::module::{Linkage, Module};
 use inkwell::passes::PassManager;
 use inkwell::targets::{
-	CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine,
+	CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
 };
 use inkwell::types::{
 	AnyType, AnyTypeEnum, ArrayType, BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType,
@@ -20,12 +21,41 @@ use inkwell::values::{
 };
 use inkwell::values::{BasicValue, FunctionValue};
 use inkwell::OptimizationLevel;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::os::raw::{c_char, c_float, c_int, c_void};
 use std::path::Path;
 
 impl CompType {
-	fn get_discriminant(&self) -> u32 {
-		hash32(self)
+	/// The tag a value of type `variant` is stored with in this union
+	fn get_tag(&self, variant: &CompType) -> Option<u32> {
+		let variant = variant.widen();
+		self.widen()
+			.get_variants()
+			.iter()
+			.position(|x| x.super_type_of(&variant))
+			.map(|tag| tag as u32)
+	}
+
+	/// Bytes a value of this type takes up in memory
+	fn get_size(&self) -> u32 {
+		use CompType::*;
+		match self.widen() {
+			Char | Bool | Null => 1,
+			Int | Float | Type => 4,
+			// The tag and a pointer to the payload
+			Enum(_, _) => 16,
+			Union(_) => 8 + self.get_payload_size(),
+			Generic(_, extends) => extends.get_size(),
+			// Everything else is passed around as a pointer
+			_ => 8,
+		}
+	}
+
+	/// Room a union needs for its largest variant, in whole words so any variant fits aligned
+	fn get_payload_size(&self) -> u32 {
+		let largest = self.widen().get_variants().iter().map(|x| x.get_size()).max();
+		largest.unwrap_or(0).div_ceil(8).max(1) * 8
 	}
 
 	fn get_compiler_type<'ctx>(
@@ -102,11 +132,28 @@ impl CompType {
 				.i32_type()
 				.ptr_type(inkwell::AddressSpace::Generic)
 				.as_basic_type_enum(),
+			// The tag of the variant and enough words to hold the largest one
 			Union(_) => context
 				.struct_type(
 					&[
 						context.i32_type().as_basic_type_enum(),
+						context
+							.i64_type()
+							.array_type(self.get_payload_size() / 8)
+							.as_basic_type_enum(),
+					],
+					false,
+				)
+				.as_basic_type_enum(),
+			// The tag of the variant and a pointer to its payload
+			Enum(_, _) => context
+				.struct_type(
+					&[
 						context.i32_type().as_basic_type_enum(),
+						context
+							.i8_type()
+							.ptr_type(inkwell::AddressSpace::Generic)
+							.as_basic_type_enum(),
 					],
 					false,
 				)
@@ -123,6 +170,67 @@ impl CompType {
 	}
 }
 
+impl OptLevel {
+	fn codegen_level(&self) -> OptimizationLevel {
+		match self {
+			OptLevel::O0 => OptimizationLevel::None,
+			OptLevel::O1 => OptimizationLevel::Less,
+			OptLevel::O2 | OptLevel::Os => OptimizationLevel::Default,
+			OptLevel::O3 => OptimizationLevel::Aggressive,
+		}
+	}
+
+	/// Passes run on each function as soon as it has been compiled
+	fn add_function_passes(&self, fpm: &PassManager<FunctionValue>) {
+		if self == &OptLevel::O0 {
+			return;
+		}
+		fpm.add_basic_alias_analysis_pass();
+		fpm.add_promote_memory_to_register_pass();
+		fpm.add_instruction_combining_pass();
+		fpm.add_cfg_simplification_pass();
+		if self == &OptLevel::O1 {
+			return;
+		}
+		fpm.add_reassociate_pass();
+		fpm.add_gvn_pass();
+		fpm.add_dead_store_elimination_pass();
+		fpm.add_instruction_combining_pass();
+		fpm.add_cfg_simplification_pass();
+		if self == &OptLevel::O3 {
+			fpm.add_aggressive_inst_combiner_pass();
+			fpm.add_loop_unroll_pass();
+			fpm.add_loop_vectorize_pass();
+			fpm.add_slp_vectorize_pass();
+		}
+	}
+
+	/// Passes run across the whole module once every function has been compiled
+	fn add_module_passes(&self, mpm: &PassManager<Module>) {
+		match self {
+			OptLevel::O0 => {}
+			OptLevel::O1 | OptLevel::Os => {
+				mpm.add_always_inliner_pass();
+				mpm.add_global_optimizer_pass();
+				mpm.add_global_dce_pass();
+				mpm.add_strip_dead_prototypes_pass();
+			}
+			OptLevel::O2 | OptLevel::O3 => {
+				mpm.add_ipsccp_pass();
+				mpm.add_function_inlining_pass();
+				mpm.add_global_optimizer_pass();
+				mpm.add_dead_arg_elimination_pass();
+				mpm.add_promote_memory_to_register_pass();
+				mpm.add_instruction_combining_pass();
+				mpm.add_cfg_simplification_pass();
+				mpm.add_global_dce_pass();
+				mpm.add_constant_merge_pass();
+				mpm.add_strip_dead_prototypes_pass();
+			}
+		}
+	}
+}
+
 pub struct Compiler<'a, 'ctx> {
 	pub context: &'ctx Context,
 	pub builder: &'a Builder<'ctx>,
@@ -131,8 +239,25 @@ pub struct Compiler<'a, 'ctx> {
 	pub dibuilder: DebugInfoBuilder<'ctx>,
 	pub compile_unit: DICompileUnit<'ctx>,
 	pub counter: u32,
+	/// The blocks `continue` and `break` jump to for each loop being compiled
+	pub loops: Vec<(BasicBlock<'ctx>, BasicBlock<'ctx>)>,
+	/// The return type of each function being compiled, so returned values can be stored to match
+	pub returns: Vec<CompType>,
+	/// Every type `typeof` has given an id so far, shared by all the modules of a program
+	pub type_ids: &'a RefCell<Vec<CompType>>,
 }
 impl<'a, 'ctx> Compiler<'a, 'ctx> {
+	/// The value `typeof` gives for this type, which is its position in the table of types seen
+	fn type_id(&self, ty: &CompType) -> BasicValueEnum<'ctx> {
+		let ty = ty.widen();
+		let mut ids = self.type_ids.borrow_mut();
+		let id = ids.iter().position(|x| *x == ty).unwrap_or_else(|| {
+			ids.push(ty);
+			ids.len() - 1
+		});
+		self.i32(id as i32)
+	}
+
 	/// Used for assigning variable names to generated variables and functions
 	pub fn get_nex_counter(&mut self) -> u32 {
 		self.counter += 1;
@@ -143,6 +268,14 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 		(self.context.i32_type().const_int(val as u64, false)).as_basic_value_enum()
 	}
 
+	/// Code after a jump can't be reached but still needs a block to go in
+	fn start_unreachable_block(&self, parent: Option<&FunctionValue<'ctx>>) {
+		let block = self
+			.context
+			.append_basic_block(*parent.unwrap(), "unreachable");
+		self.builder.position_at_end(block);
+	}
+
 	fn i64(&self, val: i64) -> BasicValueEnum<'ctx> {
 		(self.context.i64_type().const_int(val as u64, false)).as_basic_value_enum()
 	}
@@ -186,6 +319,30 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 			.map(|x| x.into_pointer_value())
 	}
 
+	/// The length of a string's text, which for templates can be shorter than their buffer
+	fn str_len(&self, str: PointerValue<'ctx>) -> Result<BasicValueEnum<'ctx>, String> {
+		let i8_ptr = self
+			.context
+			.i8_type()
+			.ptr_type(inkwell::AddressSpace::Generic);
+		let strlen = self.module.get_function("strlen").unwrap_or_else(|| {
+			let fn_val = self
+				.context
+				.i64_type()
+				.fn_type(&[BasicMetadataTypeEnum::from(i8_ptr)], false);
+			self.module
+				.add_function("strlen", fn_val, Some(Linkage::External))
+		});
+		let str = self.builder.build_pointer_cast(str, i8_ptr, "cast");
+		let len = self
+			.builder
+			.build_call(strlen, &[BasicMetadataValueEnum::from(str)], "strlen")
+			.try_as_basic_value()
+			.left()
+			.ok_or_else(|| "Invalid return from function".to_string())?;
+		Ok(self.cast_to_i32(len.into_int_value()).into())
+	}
+
 	fn cast_to_i32(&self, int: IntValue<'ctx>) -> IntValue<'ctx> {
 		self.builder
 			.build_int_cast(int, self.context.i32_type(), "int_cast")
@@ -196,6 +353,27 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 			.build_int_cast(int, self.context.i8_type(), "int_cast")
 	}
 
+	/// Chars and ints have different widths so one side has to be cast before they're combined
+	fn match_int_widths(
+		&self,
+		lhs: BasicValueEnum<'ctx>,
+		rhs: BasicValueEnum<'ctx>,
+		result_ty: &CompType,
+	) -> (BasicValueEnum<'ctx>, BasicValueEnum<'ctx>) {
+		match (lhs, rhs) {
+			(BasicValueEnum::IntValue(a), BasicValueEnum::IntValue(b))
+				if a.get_type().get_bit_width() != b.get_type().get_bit_width() =>
+			{
+				if result_ty.is_char() {
+					(self.cast_to_i8(a).into(), self.cast_to_i8(b).into())
+				} else {
+					(self.cast_to_i32(a).into(), self.cast_to_i32(b).into())
+				}
+			}
+			x => x,
+		}
+	}
+
 	fn load_variable(
 		&self,
 		variables: &HashMap<String, PointerValue<'ctx>>,
@@ -216,6 +394,127 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 			.expect("Tried to extract non existent element from struct")
 	}
 
+	/// Points into the payload of a union stored at `union`, as whatever type is kept there
+	fn union_payload(
+		&self,
+		union: PointerValue<'ctx>,
+		ty: BasicTypeEnum<'ctx>,
+	) -> PointerValue<'ctx> {
+		let payload = self.builder.build_struct_gep(union, 1, "payload").unwrap();
+		self.builder.build_pointer_cast(
+			payload,
+			ty.ptr_type(inkwell::AddressSpace::Generic),
+			"payload_cast",
+		)
+	}
+
+	/// Changes how a value is stored when it widens into a union, moves between unions or gets
+	/// taken out of one after narrowing
+	fn convert_value(
+		&self,
+		val: BasicValueEnum<'ctx>,
+		from: &CompType,
+		to: &CompType,
+		fn_val: Option<&FunctionValue<'ctx>>,
+	) -> Result<BasicValueEnum<'ctx>, String> {
+		let (from, to) = (from.widen(), to.widen());
+		if from == to || !(from.is_union() || to.is_union()) {
+			return Ok(val);
+		}
+		let fn_val = fn_val.ok_or("Unions can only be converted inside a function")?;
+		let source = self.add_variable_to_block("union_from", val.get_type(), fn_val);
+		self.builder.build_store(source, val);
+		if !to.is_union() {
+			// Narrowing has already checked the tag, so the payload holds this type
+			let payload = self.union_payload(source, to.get_compiler_type(self.context)?);
+			return Ok(self.builder.build_load(payload, "narrowed"));
+		}
+		let target_ty = to.get_compiler_type(self.context)?;
+		let target = self.add_variable_to_block("union_to", target_ty, fn_val);
+		let tag = if from.is_union() {
+			let current = self
+				.builder
+				.build_load(self.builder.build_struct_gep(source, 0, "tag").unwrap(), "tag")
+				.into_int_value();
+			// Each union numbers its own variants, so look up where each one lives in the new union
+			let mut tag = self.i32(0);
+			for (i, variant) in from.get_variants().iter().enumerate() {
+				if let Some(new_tag) = to.get_tag(variant) {
+					let is_variant = self.builder.build_int_compare(
+						inkwell::IntPredicate::EQ,
+						current,
+						self.i32(i as i32).into_int_value(),
+						"is_variant",
+					);
+					tag = self
+						.builder
+						.build_select(is_variant, self.i32(new_tag as i32), tag, "tag");
+				}
+			}
+			// Only the smaller payload is copied so nothing is read or written past either end
+			let words = from.get_payload_size().min(to.get_payload_size()) / 8;
+			let words_ty = self.context.i64_type().array_type(words).as_basic_type_enum();
+			let payload = self
+				.builder
+				.build_load(self.union_payload(source, words_ty), "payload");
+			self.builder
+				.build_store(self.union_payload(target, words_ty), payload);
+			tag
+		} else {
+			let tag = to
+				.get_tag(&from)
+				.ok_or_else(|| format!("'{}' can't be stored in '{}'", from, to))?;
+			self.builder
+				.build_store(self.union_payload(target, val.get_type()), val);
+			self.i32(tag as i32)
+		};
+		self.builder
+			.build_store(self.builder.build_struct_gep(target, 0, "tag").unwrap(), tag);
+		Ok(self.builder.build_load(target, "union"))
+	}
+
+	/// Compares a union with a value of one of its variants, which needs the tag to match too
+	fn compare_union(
+		&self,
+		op: &Op,
+		union: BasicValueEnum<'ctx>,
+		union_ty: &CompType,
+		other: BasicValueEnum<'ctx>,
+		other_ty: &CompType,
+		fn_val: Option<&FunctionValue<'ctx>>,
+	) -> Result<BasicValueEnum<'ctx>, String> {
+		let tag = match union_ty.get_tag(other_ty) {
+			Some(tag) => tag,
+			None => return Ok(self.custom_int(1, (op == &Op::Neq) as i8)),
+		};
+		let current = self.extract_element(union.into_struct_value(), 0);
+		let mut equal = self.builder.build_int_compare(
+			inkwell::IntPredicate::EQ,
+			current.into_int_value(),
+			self.i32(tag as i32).into_int_value(),
+			"same_variant",
+		);
+		let other_widened = match other_ty.widen() {
+			CompType::Generic(_, ty) => ty.widen(),
+			ty => ty,
+		};
+		match other_widened {
+			CompType::Null => {}
+			CompType::Int | CompType::Float | CompType::Char | CompType::Bool => {
+				let payload = self.convert_value(union, union_ty, other_ty, fn_val)?;
+				let same = self.comp_bin_op(&Op::Eq, payload, other, fn_val)?;
+				equal = self
+					.builder
+					.build_and(equal, same.into_int_value(), "equal");
+			}
+			ty => unreachable!("Comparing a union with '{}' is rejected when checking types", ty),
+		}
+		if op == &Op::Neq {
+			equal = self.builder.build_not(equal, "not_equal");
+		}
+		Ok(equal.as_basic_value_enum())
+	}
+
 	fn calc_pos(
 		&mut self,
 		arr: &ExpEnvironment,
@@ -297,18 +596,29 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 			Div => builder
 				.build_float_div(lhs, rhs, "div")
 				.as_basic_value_enum(),
+			Mod => builder
+				.build_float_rem(lhs, rhs, "mod")
+				.as_basic_value_enum(),
 			Eq => builder
 				.build_float_compare(inkwell::FloatPredicate::OEQ, lhs, rhs, "equal")
 				.as_basic_value_enum(),
 			Neq => builder
 				.build_float_compare(inkwell::FloatPredicate::ONE, lhs, rhs, "equal")
 				.as_basic_value_enum(),
-			Le => builder
+			Lt => builder
 				.build_float_compare(inkwell::FloatPredicate::OLT, lhs, rhs, "Lessthan")
 				.as_basic_value_enum(),
-			Ge => builder
+			Gt => builder
 				.build_float_compare(inkwell::FloatPredicate::OGT, lhs, rhs, "greaterthan")
 				.as_basic_value_enum(),
+			Le => builder
+				.build_float_compare(inkwell::FloatPredicate::OLE, lhs, rhs, "lessequal")
+				.as_basic_value_enum(),
+			Ge => builder
+				.build_float_compare(inkwell::FloatPredicate::OGE, lhs, rhs, "greaterequal")
+				.as_basic_value_enum(),
+			// The type checker only allows these on ints and bools
+			And | Or | BitAnd | BitOr | BitXor | Shl | Shr => unreachable!(),
 		}
 	}
 
@@ -333,18 +643,37 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 			Div => builder
 				.build_int_signed_div(lhs, rhs, "div")
 				.as_basic_value_enum(),
+			Mod => builder
+				.build_int_signed_rem(lhs, rhs, "mod")
+				.as_basic_value_enum(),
 			Eq => builder
 				.build_int_compare(inkwell::IntPredicate::EQ, lhs, rhs, "equal")
 				.as_basic_value_enum(),
 			Neq => builder
 				.build_int_compare(inkwell::IntPredicate::NE, lhs, rhs, "equal")
 				.as_basic_value_enum(),
-			Le => builder
+			Lt => builder
 				.build_int_compare(inkwell::IntPredicate::SLT, lhs, rhs, "Lessthan")
 				.as_basic_value_enum(),
-			Ge => builder
+			Gt => builder
 				.build_int_compare(inkwell::IntPredicate::SGT, lhs, rhs, "Lessthan")
 				.as_basic_value_enum(),
+			Le => builder
+				.build_int_compare(inkwell::IntPredicate::SLE, lhs, rhs, "lessequal")
+				.as_basic_value_enum(),
+			Ge => builder
+				.build_int_compare(inkwell::IntPredicate::SGE, lhs, rhs, "greaterequal")
+				.as_basic_value_enum(),
+			// Only reached for bools when short circuiting isn't needed
+			And | BitAnd => builder.build_and(lhs, rhs, "and").as_basic_value_enum(),
+			Or | BitOr => builder.build_or(lhs, rhs, "or").as_basic_value_enum(),
+			BitXor => builder.build_xor(lhs, rhs, "xor").as_basic_value_enum(),
+			Shl => builder
+				.build_left_shift(lhs, rhs, "shl")
+				.as_basic_value_enum(),
+			Shr => builder
+				.build_right_shift(lhs, rhs, true, "shr")
+				.as_basic_value_enum(),
 		}
 	}
 
@@ -419,11 +748,141 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 		})
 	}
 
+	/// Only compiles the right side of `&&` and `||` if it can change the result
+	fn compile_short_circuit(
+		&mut self,
+		op: &Op,
+		left: &ExpEnvironment,
+		right: &ExpEnvironment,
+		variables: &mut HashMap<String, PointerValue<'ctx>>,
+		fn_val: FunctionValue<'ctx>,
+	) -> Result<BasicValueEnum<'ctx>, String> {
+		let lhs = self
+			.compile_expression(left, variables, Some(&fn_val))?
+			.into_int_value();
+		let lhs_bb = self.builder.get_insert_block().unwrap();
+		let rhs_bb = self.context.append_basic_block(fn_val, "rhs");
+		let merge_bb = self.context.append_basic_block(fn_val, "logic_merge");
+		if op == &Op::And {
+			self.builder
+				.build_conditional_branch(lhs, rhs_bb, merge_bb);
+		} else {
+			self.builder
+				.build_conditional_branch(lhs, merge_bb, rhs_bb);
+		}
+
+		self.builder.position_at_end(rhs_bb);
+		let rhs = self
+			.compile_expression(right, variables, Some(&fn_val))?
+			.into_int_value();
+		self.builder.build_unconditional_branch(merge_bb);
+		let rhs_bb = self.builder.get_insert_block().unwrap();
+
+		self.builder.position_at_end(merge_bb);
+		let phi = self.builder.build_phi(self.context.bool_type(), "logic");
+		phi.add_incoming(&[(&lhs, lhs_bb), (&rhs, rhs_bb)]);
+		Ok(phi.as_basic_value())
+	}
+
+	/// Writes the parts of a template into a buffer big enough for the longest possible result using sprintf
+	fn compile_template(
+		&mut self,
+		parts: &[ExpEnvironment],
+		ty: &CompType,
+		variables: &mut HashMap<String, PointerValue<'ctx>>,
+		fn_val: &FunctionValue<'ctx>,
+	) -> Result<BasicValueEnum<'ctx>, String> {
+		let i8_ptr = self
+			.context
+			.i8_type()
+			.ptr_type(inkwell::AddressSpace::Generic);
+		let mut format = String::new();
+		let mut args = Vec::new();
+		for part in parts {
+			if let CompExpression::Value(CompData::Str(text)) = part.expression.as_ref() {
+				format += &text.replace('%', "%%");
+				continue;
+			}
+			let val = self.compile_expression(part, variables, Some(fn_val))?;
+			let part_ty = match part.result_type.widen() {
+				CompType::Generic(_, ty) => ty.widen(),
+				ty => ty,
+			};
+			let (spec, val) = match part_ty {
+				CompType::Int => ("%d", val),
+				// Varargs are promoted to at least an int
+				CompType::Char => ("%c", self.cast_to_i32(val.into_int_value()).into()),
+				CompType::Float => (
+					"%g",
+					self.builder
+						.build_float_ext(val.into_float_value(), self.context.f64_type(), "")
+						.into(),
+				),
+				CompType::Bool => (
+					"%s",
+					self.builder.build_select(
+						val.into_int_value(),
+						self.builder
+							.build_global_string_ptr("true", "true_str")
+							.as_pointer_value(),
+						self.builder
+							.build_global_string_ptr("false", "false_str")
+							.as_pointer_value(),
+						"bool_str",
+					),
+				),
+				CompType::Null => {
+					format += "null";
+					continue;
+				}
+				CompType::Str(_) => (
+					"%s",
+					self.builder
+						.build_pointer_cast(val.into_pointer_value(), i8_ptr, "cast")
+						.into(),
+				),
+				ty => return Err(format!("Cannot put type '{}' into a template string", ty)),
+			};
+			format += spec;
+			args.push(BasicMetadataValueEnum::from(val));
+		}
+		let len = match ty {
+			CompType::Str(len) => match len.as_ref() {
+				CompType::Constant(ConstantData::Int(len)) => *len as u32,
+				_ => return Err("Template strings need a known length".to_string()),
+			},
+			_ => return Err("Template strings must be strings".to_string()),
+		};
+		let target_type = self.context.i8_type().array_type(len + 1);
+		let target = self.add_variable_to_block("template", target_type, fn_val);
+		let sprintf = self.module.get_function("sprintf").unwrap_or_else(|| {
+			let fn_type = self
+				.context
+				.i32_type()
+				.fn_type(&[i8_ptr.into(), i8_ptr.into()], true);
+			self.module
+				.add_function("sprintf", fn_type, Some(Linkage::External))
+		});
+		let format = self
+			.builder
+			.build_global_string_ptr(&format, "template_format")
+			.as_pointer_value();
+		let target_ptr = self.builder.build_pointer_cast(target, i8_ptr, "cast");
+		let mut argv = vec![
+			BasicMetadataValueEnum::from(target_ptr),
+			BasicMetadataValueEnum::from(format),
+		];
+		argv.append(&mut args);
+		self.builder.build_call(sprintf, &argv, "template");
+		Ok(target.as_basic_value_enum())
+	}
+
 	fn get_value(&mut self, val: &CompData) -> Result<BasicValueEnum<'ctx>, String> {
 		Ok(match val {
 			CompData::Int(int) => self.i32(*int),
 			CompData::Bool(bool) => self.custom_int(1, *bool as i8),
 			CompData::Float(float) => self.f32(*float),
+			CompData::Char(char) => self.i8(*char as i8),
 			CompData::Null => self.custom_int(1, 0),
 			CompData::Str(str) => {
 				unsafe { self.builder.build_global_string(str, "string_ptr") }.as_basic_value_enum()
@@ -497,12 +956,23 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 		parent: Option<&FunctionValue<'ctx>>,
 	) -> Result<BasicValueEnum<'ctx>, String> {
 		Ok(match exp.expression.as_ref() {
-			CompExpression::Call(var, _, args) => {
-				let compiled_args = map_vec!(args, |arg| {
-					let val = self
-						.compile_expression(arg, variables, parent)
-						.expect("Cannot convert to result yet");
-					if arg.result_type.is_str() {
+			CompExpression::Call(callee, _, args) => {
+				let func = self
+					.compile_expression(callee, variables, parent)?
+					.into_pointer_value();
+				let params = match &callee.result_type {
+					CompType::Callible(params, _) => params.clone(),
+					_ => Vec::new(),
+				};
+				let mut compiled_args = Vec::new();
+				for (i, arg) in args.iter().enumerate() {
+					let val = self.compile_expression(arg, variables, parent)?;
+					// Arguments going into a union parameter have to be stored like one
+					let val = match params.get(i) {
+						Some(param) => self.convert_value(val, &arg.result_type, param, parent)?,
+						None => val,
+					};
+					compiled_args.push(if arg.result_type.is_str() && val.is_pointer_value() {
 						self.builder
 							.build_pointer_cast(
 								val.into_pointer_value(),
@@ -514,33 +984,74 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 							.as_basic_value_enum()
 					} else {
 						val
-					}
-				});
+					});
+				}
 				let argv = map_vec!(compiled_args, |x| BasicMetadataValueEnum::from(*x));
-				let func = self
-					.load_variable(variables, &var.get_name())
-					.into_pointer_value();
 				self.builder
 					.build_call(
 						CallableValue::try_from(func).unwrap(),
 						argv.as_slice(),
-						&var.get_name(),
+						&callee.get_name(),
 					)
 					.try_as_basic_value()
 					.left()
 					.ok_or_else(|| "Invalid function call produced".to_string())?
 			}
+			CompExpression::BinOp(op @ (Op::And | Op::Or), left, right) => {
+				self.compile_short_circuit(op, left, right, variables, *parent.unwrap())?
+			}
+			CompExpression::BinOp(op @ (Op::Eq | Op::Neq), left, right)
+				if left.result_type.is_union() != right.result_type.is_union() =>
+			{
+				let lhs = self.compile_expression(left, variables, parent)?;
+				let rhs = self.compile_expression(right, variables, parent)?;
+				if left.result_type.is_union() {
+					self.compare_union(op, lhs, &left.result_type, rhs, &right.result_type, parent)?
+				} else {
+					self.compare_union(op, rhs, &right.result_type, lhs, &left.result_type, parent)?
+				}
+			}
 			CompExpression::BinOp(op, left, right) => {
 				let lhs = self.compile_expression(left, variables, parent)?;
 				let rhs = self.compile_expression(right, variables, parent)?;
-				self.comp_bin_op(op, lhs, rhs, parent)?
+				let (lhs, rhs) = self.match_int_widths(lhs, rhs, &exp.result_type);
+				let val = self.comp_bin_op(op, lhs, rhs, parent)?;
+				// Subtracting chars gives the distance between them as an int
+				if left.result_type.is_char() && exp.result_type.is_int() {
+					self.cast_to_i32(val.into_int_value()).as_basic_value_enum()
+				} else {
+					val
+				}
+			}
+			CompExpression::OneOp(op, exp) => {
+				let val = self.compile_expression(exp, variables, parent)?;
+				match (op, val) {
+					(Prefix::Neg, BasicValueEnum::FloatValue(float)) => self
+						.builder
+						.build_float_neg(float, "neg")
+						.as_basic_value_enum(),
+					(Prefix::Neg, BasicValueEnum::IntValue(int)) => {
+						self.builder.build_int_neg(int, "neg").as_basic_value_enum()
+					}
+					(Prefix::Not | Prefix::BitNot, BasicValueEnum::IntValue(int)) => {
+						self.builder.build_not(int, "not").as_basic_value_enum()
+					}
+					(op, val) => {
+						return Err(format!(
+							"Operator '{}' cannot be compiled for {:?}",
+							op, val
+						))
+					}
+				}
 			}
 			CompExpression::Read(var) => self.load_variable(variables, &var.get_name()),
 			CompExpression::Conversion(exp, ty) => {
 				let val = self.compile_expression(exp, variables, parent)?;
 				// Assume only int and char conversions for now
 				// TODO: implement properly
-				if ty.is_int_ptr(){
+				if exp.result_type.is_union() || ty.is_union() {
+					self.convert_value(val, &exp.result_type, ty, parent)?
+				} else if ty.is_int_ptr(){
 					self.builder.build_bitcast(val.into_pointer_value(), 				self
 					.context
 					.i32_type()
@@ -599,6 +1110,8 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 						}
 					}
 				}
+				let assigned = val;
+				let val = self.convert_value(val, &exp.result_type, &mem_ty, parent)?;
 				// This is hacky but should be safe as casts should've been checked sooner
 				let val = if val.get_type().as_any_type_enum()!=mem_ptr.get_type().get_element_type() && !mem_ty.is_union(){
 					eprintln!("{}",mem.variable.name);
@@ -606,41 +1119,8 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 				}else {
 					val
 				};
-
-				if mem_ty.is_union() {
-					let ty = &exp.result_type;
-					if !ty.is_union() {
-						let discriminant = self.i32(ty.get_discriminant() as i32);
-						let val = if val.is_pointer_value() {
-							self.builder
-								.build_ptr_to_int(
-									val.into_pointer_value(),
-									self.context.i32_type(),
-									"",
-								)
-								.as_basic_value_enum()
-						} else {
-							self.builder.build_bitcast(
-								val,
-								self.context.i32_type().as_basic_type_enum(),
-								"",
-							)
-						};
-						self.builder.build_store(
-							self.builder.build_struct_gep(mem_ptr, 0, "").unwrap(),
-							discriminant,
-						);
-						self.builder.build_store(
-							self.builder.build_struct_gep(mem_ptr, 1, "").unwrap(),
-							val,
-						);
-					} else {
-						self.builder.build_store(mem_ptr, val);
-					}
-				} else {
-					self.builder.build_store(mem_ptr, val);
-				}
-				val
+				self.builder.build_store(mem_ptr, val);
+				assigned
 			}
 			CompExpression::Value(val) => self.get_value(val)?,
 			CompExpression::IfElse(if_exp) => {
@@ -655,55 +1135,122 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 				self.builder
 					.build_conditional_branch(cond, then_bb, else_bb);
 
+				// Branches that jump away never reach the merge block
+				let then_jumps = if_exp.then.result_type == NEVER;
+				let else_jumps = if_exp.otherwise.result_type == NEVER;
+
 				// build then block
 				self.builder.position_at_end(then_bb);
 				let then_val = self.compile_expression(&if_exp.then, variables, parent)?;
-				self.builder.build_unconditional_branch(cont_bb);
+				// Both branches are converted to the if's type, like a union, to share a phi
+				let then_val = if then_jumps {
+					then_val
+				} else {
+					let then_ty = &if_exp.then.result_type;
+					self.convert_value(then_val, then_ty, &exp.result_type, parent)?
+				};
+				if then_jumps {
+					self.builder.build_unreachable();
+				} else {
+					self.builder.build_unconditional_branch(cont_bb);
+				}
 
 				let then_bb = self.builder.get_insert_block().unwrap();
 
 				// build else block
 				self.builder.position_at_end(else_bb);
 				let else_val = self.compile_expression(&if_exp.otherwise, variables, parent)?;
-				self.builder.build_unconditional_branch(cont_bb);
+				let else_val = if else_jumps {
+					else_val
+				} else {
+					let else_ty = &if_exp.otherwise.result_type;
+					self.convert_value(else_val, else_ty, &exp.result_type, parent)?
+				};
+				if else_jumps {
+					self.builder.build_unreachable();
+				} else {
+					self.builder.build_unconditional_branch(cont_bb);
+				}
 
 				let else_bb = self.builder.get_insert_block().unwrap();
 
 				// emit merge block
 				self.builder.position_at_end(cont_bb);
 
-				let phi = self.builder.build_phi(self.context.i32_type(), "iftmp");
+				match (then_jumps, else_jumps) {
+					(true, false) => return Ok(else_val),
+					// If both jump the merge block can't be reached, so nothing uses the value
+					(_, true) => return Ok(then_val),
+					(false, false) => {}
+				}
+				if then_val.get_type() != else_val.get_type() {
+					return Err(format!(
+						"The branches of an if can't both be stored as '{}'",
+						exp.result_type
+					));
+				}
+				let phi = self.builder.build_phi(then_val.get_type(), "iftmp");
 
 				phi.add_incoming(&[(&then_val, then_bb), (&else_val, else_bb)]);
 
 				phi.as_basic_value()
 			}
 			CompExpression::WhileLoop { cond, body } => {
+				// The condition gets its own block so continue can jump back to it
+				let cond_bb = self
+					.context
+					.append_basic_block(*parent.unwrap(), "loopcond");
 				let loop_bb = self.context.append_basic_block(*parent.unwrap(), "loop");
-				// compile end condition
-				let end_cond = self
-					.compile_expression(cond, variables, parent)?
-					.into_int_value();
 				let after_bb = self
 					.context
 					.append_basic_block(*parent.unwrap(), "afterloop");
-				self.builder
-					.build_conditional_branch(end_cond, loop_bb, after_bb);
-
-				self.builder.position_at_end(loop_bb);
+				self.builder.build_unconditional_branch(cond_bb);
 
-				// emit body
-				self.compile_expression(body, variables, parent)?;
 				// compile end condition
+				self.builder.position_at_end(cond_bb);
 				let end_cond = self
 					.compile_expression(cond, variables, parent)?
 					.into_int_value();
-
 				self.builder
 					.build_conditional_branch(end_cond, loop_bb, after_bb);
+
+				// emit body
+				self.builder.position_at_end(loop_bb);
+				self.loops.push((cond_bb, after_bb));
+				let body = self.compile_expression(body, variables, parent);
+				self.loops.pop();
+				body?;
+				self.builder.build_unconditional_branch(cond_bb);
+
 				self.builder.position_at_end(after_bb);
 				self.i64(0)
 			}
+			CompExpression::Break | CompExpression::Continue => {
+				let (cond_bb, after_bb) = *self
+					.loops
+					.last()
+					.ok_or("Can't jump out of a loop when not in one")?;
+				if matches!(exp.expression.as_ref(), CompExpression::Break) {
+					self.builder.build_unconditional_branch(after_bb);
+				} else {
+					self.builder.build_unconditional_branch(cond_bb);
+				}
+				self.start_unreachable_block(parent);
+				self.i32(0)
+			}
+			CompExpression::Return(val) => {
+				let value = self.compile_expression(val, variables, parent)?;
+				let return_type = self.returns.last().cloned().unwrap_or(CompType::Null);
+				if val.result_type == CompType::Null && !return_type.is_union() {
+					self.builder.build_return(Some(&self.custom_int(1, 0)));
+				} else {
+					let value = self.convert_value(value, &val.result_type, &return_type, parent)?;
+					self.builder.build_return(Some(&value));
+				}
+				self.start_unreachable_block(parent);
+				// Only unreachable code can use this, so it's fine that it came from another block
+				value
+			}
 			CompExpression::List(expressions) => {
 				let mut last = None;
 				for x in expressions {
@@ -725,6 +1272,10 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 						.unwrap();
 					self.builder.build_load(ptr, "")
 				}
+				CompType::Str(_) if key == "length" => {
+					let val = self.compile_expression(val, variables, parent)?;
+					self.str_len(val.into_pointer_value())?
+				}
 				CompType::Array(_, len) => {
 					if key == "length"&&let CompType::Constant(len)=len.as_ref() {
 						let len = len.clone().to_comp_data();
@@ -739,24 +1290,41 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 				// TODO: Optimise away once we introduce purity specifiers
 				let res_exp = self.compile_expression(exp, variables, parent)?;
 				if exp.result_type.is_union() {
-					self.extract_element(res_exp.into_struct_value(), 0)
+					let tag = self.extract_element(res_exp.into_struct_value(), 0);
+					let mut id = self.i32(0);
+					for (i, variant) in exp.result_type.widen().get_variants().iter().enumerate() {
+						let is_variant = self.builder.build_int_compare(
+							inkwell::IntPredicate::EQ,
+							tag.into_int_value(),
+							self.i32(i as i32).into_int_value(),
+							"is_variant",
+						);
+						let variant_id = self.type_id(variant);
+						id = self.builder.build_select(is_variant, variant_id, id, "type_id");
+					}
+					id
 				} else {
-					self.i32(exp.result_type.get_discriminant() as i32)
+					self.type_id(&exp.result_type)
 				}
 			}
 			CompExpression::Struct(fields) => {
-				let mem = self.builder.build_pointer_cast(
-					// TODO: Actually calculate it instead of guessing
-					self.alloc_heap(fields.len() * 4)?,
-					exp.result_type
-						.get_compiler_type(self.context)?
-						.into_pointer_type(),
-					"",
-				);
-				if let CompType::Struct(_keys) = &exp.result_type {
+				if let CompType::Struct(keys) = &exp.result_type {
+					// Every field gets a whole word or more, which leaves room for any padding
+					let size: u32 = keys.values().map(|ty| ty.get_size().div_ceil(8) * 8).sum();
+					let mem = self.builder.build_pointer_cast(
+						self.alloc_heap(size as usize)?,
+						exp.result_type
+							.get_compiler_type(self.context)?
+							.into_pointer_type(),
+						"",
+					);
 					let raw_data: Vec<BasicValueEnum<'ctx>> = fields
 						.values()
-						.map(|(_, exp)|self.compile_expression(exp, variables, parent))
+						.zip(keys.values())
+						.map(|((_, exp), ty)| {
+							let val = self.compile_expression(exp, variables, parent)?;
+							self.convert_value(val, &exp.result_type, ty, parent)
+						})
 						.collect::<Result<_, _>>()?;
 					for (i, element) in raw_data.iter().enumerate() {
 						let ptr = self
@@ -770,6 +1338,69 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 					unreachable!()
 				}
 			}
+			CompExpression::Template(parts) => self.compile_template(
+				parts,
+				&exp.result_type,
+				variables,
+				parent.ok_or_else(|| "No function provided".to_string())?,
+			)?,
+			CompExpression::Variant(ty, tag, payload) => {
+				let payload = self.compile_expression(payload, variables, parent)?;
+				let payload = self.builder.build_pointer_cast(
+					payload.into_pointer_value(),
+					self.context.i8_type().ptr_type(inkwell::AddressSpace::Generic),
+					"payload",
+				);
+				let value = ty.get_compiler_type(self.context)?.into_struct_type().get_undef();
+				let value = self
+					.builder
+					.build_insert_value(value, self.i32(*tag as i32), 0, "variant_tag")
+					.unwrap();
+				self.builder
+					.build_insert_value(value, payload, 1, "variant")
+					.unwrap()
+					.into_struct_value()
+					.as_basic_value_enum()
+			}
+			CompExpression::Is(val, ty) => {
+				let value = self.compile_expression(val, variables, parent)?;
+				if val.result_type.is_union() {
+					let current = self.extract_element(value.into_struct_value(), 0);
+					let mut is = self.custom_int(1, 0).into_int_value();
+					let variants = val.result_type.widen().get_variants();
+					for (tag, variant) in variants.iter().enumerate() {
+						if ty.super_type_of(variant) {
+							let matches = self.builder.build_int_compare(
+								inkwell::IntPredicate::EQ,
+								current.into_int_value(),
+								self.i32(tag as i32).into_int_value(),
+								"is_variant",
+							);
+							is = self.builder.build_or(is, matches, "is");
+						}
+					}
+					is.as_basic_value_enum()
+				} else {
+					self.custom_int(1, ty.super_type_of(&val.result_type) as i8)
+				}
+			}
+			CompExpression::Tag(val) => {
+				let val = self.compile_expression(val, variables, parent)?;
+				self.extract_element(val.into_struct_value(), 0)
+			}
+			CompExpression::Payload(val, _) => {
+				let val = self.compile_expression(val, variables, parent)?;
+				let payload = self.extract_element(val.into_struct_value(), 1);
+				self.builder
+					.build_pointer_cast(
+						payload.into_pointer_value(),
+						exp.result_type
+							.get_compiler_type(self.context)?
+							.into_pointer_type(),
+						"payload",
+					)
+					.as_basic_value_enum()
+			}
 			other => return Err(format!("Not implemented '{:?}'", other)),
 		})
 	}
@@ -887,10 +1518,22 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 			}
 		}
 		// compile body
-		let body = self.compile_expression(&prog.body, &mut variables, Some(&fn_val))?;
+		self.returns.push(func.return_type.clone());
+		let body = self.compile_expression(&prog.body, &mut variables, Some(&fn_val));
+		self.returns.pop();
+		let body = body?;
 		match func.return_type {
+			_ if prog.body.result_type == NEVER => self.builder.build_unreachable(),
 			CompType::Null => self.builder.build_return(Some(&self.custom_int(1, 0))),
-			_ => self.builder.build_return(Some(&body)),
+			_ => {
+				let body = self.convert_value(
+					body,
+					&prog.body.result_type,
+					&func.return_type,
+					Some(&fn_val),
+				)?;
+				self.builder.build_return(Some(&body))
+			}
 		};
 
 		// return the whole thing after verification and optimization
@@ -948,8 +1591,13 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
 	}
 }
 
-pub fn compile(ast: &Program, settings: Settings) -> Result<(), String> {
-	let ctx = Context::create();
+/// Generates and optimizes the llvm module for a single file, giving types the ids in `type_ids`
+pub fn build_module<'ctx>(
+	ctx: &'ctx Context,
+	ast: &Program,
+	settings: &Settings,
+	type_ids: &RefCell<Vec<CompType>>,
+) -> Result<Module<'ctx>, String> {
 	let module = ctx.create_module("repl");
 	let builder = ctx.create_builder();
 	let debug_metadata_version = ctx.i32_type().const_int(3, false);
@@ -964,7 +1612,7 @@ pub fn compile(ast: &Program, settings: Settings) -> Result<(), String> {
 		/* filename */ &settings.input_name,
 		/* directory */ ".",
 		/* producer */ "Stream compiler",
-		/* is_optimized */ !settings.skip_optimizations,
+		/* is_optimized */ settings.opt_level != OptLevel::O0,
 		/* compiler command line flags */ "Fill in later",
 		/* runtime_ver */ 0,
 		/* split_name */ "",
@@ -980,27 +1628,23 @@ pub fn compile(ast: &Program, settings: Settings) -> Result<(), String> {
 
 	// Create FPM
 	let fpm = PassManager::create(&module);
-	if settings.skip_optimizations {
-		fpm.add_instruction_combining_pass();
-		fpm.add_reassociate_pass();
-		fpm.add_gvn_pass();
-		fpm.add_cfg_simplification_pass();
-		fpm.add_basic_alias_analysis_pass();
-		fpm.add_promote_memory_to_register_pass();
-		fpm.add_instruction_combining_pass();
-		fpm.add_reassociate_pass();
-	};
+	settings.opt_level.add_function_passes(&fpm);
 	fpm.initialize();
+	let mpm = PassManager::create(());
+	settings.opt_level.add_module_passes(&mpm);
 	// make module
 	let module = ctx.create_module("module");
 	let mut compiler = Compiler {
-		context: &ctx,
+		context: ctx,
 		builder: &builder,
 		fpm: &fpm,
 		module: &module,
 		dibuilder,
 		compile_unit,
 		counter: 0,
+		loops: Vec::new(),
+		returns: Vec::new(),
+		type_ids,
 	};
 
 	for (name, var) in ast.scope.get_variables() {
@@ -1011,30 +1655,152 @@ pub fn compile(ast: &Program, settings: Settings) -> Result<(), String> {
 	}
 
 	compiler.compile_expression(&ast.body, &mut HashMap::new(), None)?;
+	mpm.run_on(compiler.module);
 
-	Target::initialize_native(&InitializationConfig::default())?;
-	let opt = OptimizationLevel::Default;
-	let reloc = RelocMode::Default;
-	let model = CodeModel::Default;
-	let path = Path::new(&settings.object_name);
-	let target =
-		Target::from_name("arm64").ok_or_else(|| "Could not find target from name".to_string())?;
-	let target_machine = target
-		.create_target_machine(
-			&TargetMachine::get_default_triple(),
-			"apple-m1",
-			"",
-			opt,
-			reloc,
-			model,
-		)
-		.ok_or_else(|| "Couldnot make target_machine".to_string())?;
+	// The compiler holds a reference to the module so we have to be done with it before handing the module back
+	drop(compiler);
+	Ok(module)
+}
 
-	target_machine
-		.write_to_file(compiler.module, FileType::Object, path)
-		.map_err(|x| x.to_string())?;
+pub fn compile(ast: &Program, settings: Settings) -> Result<(), String> {
+	let ctx = Context::create();
+	let module = build_module(&ctx, ast, &settings, &RefCell::new(Vec::new()))?;
+	let target_machine = create_target_machine(&settings)?;
+	for emit in &settings.emit {
+		let path = Path::new(&settings.object_name).with_extension(emit.extension());
+		match emit {
+			Emit::Object => target_machine
+				.write_to_file(&module, FileType::Object, &path)
+				.map_err(|x| x.to_string())?,
+			Emit::Assembly => target_machine
+				.write_to_file(&module, FileType::Assembly, &path)
+				.map_err(|x| x.to_string())?,
+			Emit::LlvmIr => module.print_to_file(&path).map_err(|x| x.to_string())?,
+			Emit::LlvmBitcode => {
+				if !module.write_bitcode_to_path(&path) {
+					return Err(format!("Failed to write bitcode to '{}'", path.display()));
+				}
+			}
+		}
+	}
 	if settings.print_llvm {
 		module.print_to_stderr();
 	}
 	Ok(())
 }
+
+/// Creates a target machine for the triple, cpu and features in the settings
+/// Falls back to the host for anything that isn't specified
+pub fn create_target_machine(settings: &Settings) -> Result<TargetMachine, String> {
+	let opt = settings.opt_level.codegen_level();
+	let reloc = RelocMode::Default;
+	let model = CodeModel::Default;
+	let (triple, cpu, features) = match &settings.target_triple {
+		Some(triple) => {
+			Target::initialize_all(&InitializationConfig::default());
+			(
+				TargetTriple::create(triple),
+				settings.cpu.clone().unwrap_or_else(|| "generic".to_string()),
+				settings.cpu_features.clone().unwrap_or_default(),
+			)
+		}
+		None => {
+			Target::initialize_native(&InitializationConfig::default())?;
+			(
+				TargetMachine::get_default_triple(),
+				settings
+					.cpu
+					.clone()
+					.unwrap_or_else(|| TargetMachine::get_host_cpu_name().to_string()),
+				settings
+					.cpu_features
+					.clone()
+					.unwrap_or_else(|| TargetMachine::get_host_cpu_features().to_string()),
+			)
+		}
+	};
+	let target = Target::from_triple(&triple)
+		.map_err(|x| format!("Unknown target triple {:?}: {}", triple.as_str(), x))?;
+	target
+		.create_target_machine(&triple, &cpu, &features, opt, reloc, model)
+		.ok_or_else(|| {
+			format!(
+				"Could not create a target machine for {:?} with cpu '{}' and features '{}'",
+				triple.as_str(),
+				cpu,
+				features
+			)
+		})
+}
+
+extern "C" {
+	fn puts(str: *const c_char) -> c_int;
+	fn putchar(char: c_int) -> c_int;
+	fn getchar() -> c_int;
+	fn malloc(size: usize) -> *mut c_void;
+	fn strcat(dest: *mut c_char, src: *const c_char) -> *mut c_char;
+	fn memcpy(dest: *mut c_void, src: *const c_void, size: usize) -> *mut c_void;
+	fn sinf(x: c_float) -> c_float;
+}
+
+/// The in process address of the libc function backing an external declaration
+fn get_host_symbol(name: &str) -> Option<usize> {
+	Some(match name {
+		"puts" => puts as usize,
+		"putchar" => putchar as usize,
+		"getchar" => getchar as usize,
+		"malloc" => malloc as usize,
+		"strcat" => strcat as usize,
+		"memcpy" => memcpy as usize,
+		// The standard library declares sin on floats so it matches sinf rather than sin
+		"sin" => sinf as usize,
+		_ => return None,
+	})
+}
+
+/// Compiles the file and everything it imports then runs its main function in process
+/// Returns the value main returned to be used as an exit code
+pub fn run_jit(name: &str, files: &HashMap<String, ImportMap>) -> Result<i32, String> {
+	let ctx = Context::create();
+	let entry = files
+		.get(name)
+		.ok_or_else(|| format!("Could not find a module at '{}'", name))?;
+	// Values can cross modules, so every module has to agree on what `typeof` gives
+	let type_ids = RefCell::new(Vec::new());
+	let build = |file_name: &str, file: &ImportMap| {
+		let prog = file
+			.program
+			.as_ref()
+			.ok_or_else(|| format!("The module '{}' has not been type checked", file_name))?;
+		build_module(&ctx, prog, &file.settings, &type_ids)
+	};
+	let main_module = build(name, entry)?;
+	let mut modules = Vec::new();
+	for (file_name, file) in files {
+		if file_name != name {
+			modules.push(build(file_name, file)?);
+		}
+	}
+	Target::initialize_native(&InitializationConfig::default())?;
+	let opt = entry.settings.opt_level.codegen_level();
+	let engine = main_module
+		.create_jit_execution_engine(opt)
+		.map_err(|x| x.to_string())?;
+	for module in &modules {
+		engine
+			.add_module(module)
+			.map_err(|_| "Failed to add module to the execution engine".to_string())?;
+	}
+	for module in std::iter::once(&main_module).chain(&modules) {
+		for func in module.get_functions() {
+			let name = func.get_name().to_string_lossy();
+			if func.count_basic_blocks() == 0 && let Some(addr) = get_host_symbol(&name) {
+				engine.add_global_mapping(&func, addr);
+			}
+		}
+	}
+	let main = main_module
+		.get_function("main")
+		.ok_or_else(|| "Could not find a main function to run".to_string())?;
+	Ok(unsafe { engine.run_function_as_main(main, &[]) })
+}
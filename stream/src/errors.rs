@@ -1,57 +1,252 @@
 use crate::ast1::*;
 use crate::ast2::*;
+use crate::runner::ImportMap;
 
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter, Result};
 use std::ops::Range;
 
 macro_rules! errors {
-    ($(($code:tt, $Name:ident $(($($arg_name:ident: $arg_type:ty),*))?, $msg:tt)),*) => {
+    ($(($code:tt, $Name:ident $(($($arg_name:ident: $arg_type:ty),*))? $([$($extra_name:ident: $extra_type:ty),*])?, $msg:tt)),*) => {
 	#[derive(Debug, PartialEq, Clone)]
 	pub enum CompError {
             $($Name(
 		$($($arg_type,)*)?
+		$($($extra_type,)*)?
 		    Range<usize>
 	    ),)*
         }
         impl CompError {
-	    fn get_code(&self)->i32{
+	    pub fn get_code(&self)->i32{
 		match self {
-		    $(CompError::$Name($($( $arg_name,)*)? _) =>{
-			// We have to pretend to use the variables otherwise the compiler complains, also we can't use _s
-			let _=($($($arg_name,)*)?);
-			$code
-		    },)*
+		    $(CompError::$Name(..) => $code,)*
 		}
 	    }
-            pub fn get_msg(&self, lines:&[i32]) -> String {
-                match self {
-		    $(CompError::$Name($($($arg_name,)*)? loc) =>{
-			let pos = get_pos(loc.start as i32, lines);
-			format!("Error [{}]: {}, at {}", self.get_code(), format!($msg, $($($arg_name,)*)?), pos)
-		    })*
-                }
-            }
 
-			pub fn get_msg_without_lines(&self) -> String {
-                match self {
-		    $(CompError::$Name($($($arg_name,)*)? loc) =>{
-			format!("Error [{}]: {}, at {:?}", self.get_code(), format!($msg, $($($arg_name,)*)?), loc)
-		    })*
-                }
-            }
-
-            pub fn get_pos(&self, lines:&[i32]) -> (FilePosition, FilePosition) {
-                match self {
-		    $(CompError::$Name($($($arg_name,)*)? loc) =>{
-			// We have to pretend to use the variables otherwise the compiler complains, also we can't use _s
-			let _=($($($arg_name,)*)?);
-			(get_pos(loc.start as i32, lines) ,get_pos(loc.end as i32, lines))
+	    /// The message for the error without its code or position
+	    pub fn get_description(&self) -> String {
+		match self {
+		    $(CompError::$Name($($($arg_name,)*)? $($($extra_name,)*)? _) =>{
+			// Extra arguments aren't part of the message but still have to be bound
+			let _=($($($extra_name,)*)?);
+			format!($msg, $($($arg_name,)*)?)
 		    })*
-                }
-            }
+		}
+	    }
+
+	    pub fn get_loc(&self) -> Range<usize> {
+		match self {
+		    $(CompError::$Name(.., loc) => loc.clone(),)*
+		}
+	    }
         }
     };
 }
+
+impl CompError {
+	/// Warnings point out likely mistakes but don't stop the program from being run
+	pub fn is_warning(&self) -> bool {
+		matches!(self, CompError::UnreachablePattern(..))
+	}
+
+	fn get_severity(&self) -> &'static str {
+		if self.is_warning() {
+			"Warning"
+		} else {
+			"Error"
+		}
+	}
+
+	pub fn get_msg(&self, lines: &[i32]) -> String {
+		let pos = get_pos(self.get_loc().start as i32, lines);
+		format!(
+			"{} [{}]: {}, at {}",
+			self.get_severity(),
+			self.get_code(),
+			self.get_description(),
+			pos
+		)
+	}
+
+	pub fn get_msg_without_lines(&self) -> String {
+		format!(
+			"{} [{}]: {}, at {:?}",
+			self.get_severity(),
+			self.get_code(),
+			self.get_description(),
+			self.get_loc()
+		)
+	}
+
+	pub fn get_pos(&self, lines: &[i32]) -> (FilePosition, FilePosition) {
+		let loc = self.get_loc();
+		(
+			get_pos(loc.start as i32, lines),
+			get_pos(loc.end as i32, lines),
+		)
+	}
+}
+
+/// A secondary location that helps explain an error
+pub struct Label {
+	pub file: String,
+	pub loc: Range<usize>,
+	pub msg: String,
+}
+
+impl CompError {
+	pub fn get_labels(&self) -> Vec<Label> {
+		match self {
+			CompError::ConstReassign(name, Some((file, loc)), _) => vec![Label {
+				file: file.clone(),
+				loc: loc.clone(),
+				msg: format!("'{}' is declared as a constant here", name),
+			}],
+			CompError::RedeclareInSameScope(name, Some((file, loc)), _) => vec![Label {
+				file: file.clone(),
+				loc: loc.clone(),
+				msg: format!("'{}' is first declared here", name),
+			}],
+			_ => Vec::new(),
+		}
+	}
+
+	/// Renders the error with the source lines it points at, along with any secondary labels
+	/// Labels in other files are looked up in `files`
+	pub fn render(
+		&self,
+		file: &ImportMap,
+		files: &HashMap<String, ImportMap>,
+		colour: bool,
+	) -> String {
+		let style = Style(colour);
+		let loc = self.get_loc();
+		let pos = get_pos(loc.start as i32, &file.line_numbers);
+		let (severity, severity_colour) = if self.is_warning() {
+			("warning", YELLOW)
+		} else {
+			("error", RED)
+		};
+		let severity = format!("{}[{}]", severity, self.get_code());
+		let mut out = format!(
+			"{}: {}\n  {} {}:{}:{}\n",
+			style.paint(severity_colour, &severity),
+			style.paint(BOLD, &self.get_description()),
+			style.paint(BLUE, "-->"),
+			file.file,
+			pos.line + 1,
+			pos.column + 1
+		);
+		if let Some(text) = &file.text {
+			render_snippet(
+				&mut out,
+				text,
+				&file.line_numbers,
+				&loc,
+				('^', severity_colour),
+				"",
+				&style,
+			);
+		}
+		for label in self.get_labels() {
+			let source = if label.file == file.file {
+				Some(file)
+			} else {
+				files.get(&label.file)
+			};
+			if let Some(source) = source && let Some(text) = &source.text {
+				if label.file != file.file {
+					let pos = get_pos(label.loc.start as i32, &source.line_numbers);
+					out += &format!(
+						"  {} {}:{}:{}\n",
+						style.paint(BLUE, ":::"),
+						label.file,
+						pos.line + 1,
+						pos.column + 1
+					);
+				}
+				render_snippet(
+					&mut out,
+					text,
+					&source.line_numbers,
+					&label.loc,
+					('-', BLUE),
+					&label.msg,
+					&style,
+				);
+			}
+		}
+		out
+	}
+}
+
+const RED: &str = "1;31";
+const YELLOW: &str = "1;33";
+const BLUE: &str = "1;34";
+const BOLD: &str = "1";
+
+struct Style(bool);
+impl Style {
+	fn paint(&self, code: &str, text: &str) -> String {
+		if self.0 {
+			format!("\x1b[{}m{}\x1b[0m", code, text)
+		} else {
+			text.to_string()
+		}
+	}
+}
+
+/// Width of some source text once tabs have been expanded
+fn display_width(text: &str) -> usize {
+	text.chars().map(|c| if c == '\t' { 4 } else { 1 }).sum()
+}
+
+/// Writes out every line the location covers with the located part underlined
+fn render_snippet(
+	out: &mut String,
+	text: &str,
+	lines: &[i32],
+	loc: &Range<usize>,
+	(marker, colour): (char, &str),
+	msg: &str,
+	style: &Style,
+) {
+	let start = loc.start.min(text.len());
+	let end = loc.end.clamp(start, text.len());
+	let first = get_pos(start as i32, lines).line as usize;
+	let last = get_pos(end.saturating_sub(1).max(start) as i32, lines).line as usize;
+	let gutter = (last + 1).to_string().len();
+	let bar = style.paint(BLUE, "|");
+	*out += &format!("{} {}\n", " ".repeat(gutter), bar);
+	for line in first..=last {
+		let line_start = lines[line] as usize;
+		let line_end = (lines[line + 1] as usize - 1).min(text.len());
+		let source = text
+			.get(line_start..line_end)
+			.unwrap_or("")
+			.trim_end_matches('\r');
+		let from = start.clamp(line_start, line_start + source.len()) - line_start;
+		let to = end.clamp(line_start, line_start + source.len()) - line_start;
+		let padding = display_width(source.get(..from).unwrap_or(""));
+		let width = display_width(source.get(from..to).unwrap_or("")).max(1);
+		*out += &format!(
+			"{} {} {}\n",
+			style.paint(BLUE, &format!("{:>width$}", line + 1, width = gutter)),
+			bar,
+			source.replace('\t', "    ")
+		);
+		let msg = if line == last { msg } else { "" };
+		let underline = format!("{} {}", marker.to_string().repeat(width), msg);
+		*out += &format!(
+			"{} {} {}{}\n",
+			" ".repeat(gutter),
+			bar,
+			" ".repeat(padding),
+			style.paint(colour, underline.trim_end())
+		);
+	}
+}
+
 pub struct FilePosition {
 	pub line: i32,
 	pub column: i32,
@@ -61,6 +256,25 @@ impl Display for FilePosition {
 		write!(f, "{}: {}", self.line, self.column)
 	}
 }
+/// What the parser was expecting when it hit an unexpected token or character
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expected {
+	pub tokens: Vec<String>,
+	pub label: Option<String>,
+}
+impl Display for Expected {
+	fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+		match self.tokens.as_slice() {
+			[] => write!(f, "something else")?,
+			[token] => write!(f, "{}", token)?,
+			[rest @ .., last] => write!(f, "one of {} or {}", rest.join(", "), last)?,
+		}
+		if let Some(label) = &self.label {
+			write!(f, " while parsing {}", label.to_lowercase())?;
+		}
+		Ok(())
+	}
+}
 pub fn get_pos(pos: i32, lines: &[i32]) -> FilePosition {
 	let line = lines.iter().position(|x| *x > pos).unwrap() - 1;
 	let column = pos - lines[line];
@@ -72,7 +286,7 @@ pub fn get_pos(pos: i32, lines: &[i32]) -> FilePosition {
 errors!(
 	(
 		1,
-		ConstReassign(name:String),
+		ConstReassign(name:String) [declared_at:Option<(String, Range<usize>)>],
 		"Cannot reassign to already declared constant '{}'"
 	),
 	(
@@ -104,9 +318,9 @@ errors!(
 	),
 	(9, BoolInIf(ty:CompType), "The comparison expression in an if expression must resolve to type  'Bool', found '{}' instead"),
 	(10, InvalidAssignment(attempted:CompType, allowed:CompType), "Type '{:?}' is not assignable to type '{:?}'"),
-	(11, NonfunctionCall(name:String, var:CompType), "Cannot call variable '{}' of type '{}'"),
+	(11, NonfunctionCall(name:String, var:CompType), "Cannot call '{}' of type '{}'"),
 	(12, UntypedExternal(name:String), "External variable '{}' must be declared with a type"),
-	(13, RedeclareInSameScope(name:String), "Cannot redeclare variable '{}' in the same scope"),
+	(13, RedeclareInSameScope(name:String) [declared_at:Option<(String, Range<usize>)>], "Cannot redeclare variable '{}' in the same scope"),
 	(14, TypeAlreadyDefined(name:String), "Type '{}' is already defined"),
 	(15, CannotIndexType(ty:CompType), "Cannot access elements of type '{}'"),
 	(16, InvalidIndexType(ty:CompType), "Cannot index array with '{}'"),
@@ -118,5 +332,94 @@ errors!(
 	(22, NotImplemented(custom_msg:String), " Not implemented: {}"),
 	(23, WrongArgumentsCount(name:String, recieved:usize, expected:usize), " Attempted to call function '{}' with '{}' arguments but expected '{}' arguments"),
 	(23, NotEnoughGenerics, " Need more generics"),
-	(24, MismatchedGenericConstraint(provided:CompType, super_ty:CompType), " Invalid generic argument provided, '{}' does not extend '{}'")
+	(24, MismatchedGenericConstraint(provided:CompType, super_ty:CompType), " Invalid generic argument provided, '{}' does not extend '{}'"),
+	(25, UnexpectedCharacter(found:String, expected:Expected), "Unexpected {}, expected {}"),
+	(26, UnexpectedToken(found:String, expected:Expected), "Unexpected {}, expected {}"),
+	(27, InvalidLiteral(reason:String), "{}"),
+	(28, CannotInterpolate(ty:CompType), "Values of type '{}' can't be put into a template string"),
+	(29, InvalidPrefixOperation(op:Prefix, ty:CompType), "Invalid operation '{}' for type '{}'"),
+	(30, InvalidReturn(found:CompType, expected:CompType), "Type '{}' can't be returned from a function that returns '{}'"),
+	(31, OutsideOfLoop(keyword:String), "'{}' can only be used inside a loop"),
+	(32, ReturnOutsideFunction, "'return' can only be used inside a function"),
+	(33, CannotIterate(ty:CompType), "Values of type '{}' can't be looped over"),
+	(34, MissingSelf(method:String), "Method '{}' needs 'self' as its first argument"),
+	(35, UnknownVariant(variant:String, ty:CompType), "'{}' is not a variant of '{}'"),
+	(36, VariantFieldCount(variant:String, expected:usize, found:usize), "Variant '{}' has {} fields but {} were given"),
+	(37, MismatchedPattern(pattern:CompType, value:CompType), "A pattern of type '{}' can't match a value of type '{}'"),
+	(38, NonExhaustiveMatch(missing:String), "Match doesn't handle {}"),
+	(39, UnreachablePattern, "This arm can never match, the arms before it already handle everything it could"),
+	(40, InvalidSyntax(reason:String), "{}")
 );
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::interpret::{Interpreter, Value};
+	use crate::runner::parse_text;
+	use crate::settings::{OptLevel, Settings};
+
+	/// Type checks the text as a whole file, giving back the program and its errors and warnings
+	fn check(src: &str) -> (Program, Vec<CompError>) {
+		let settings = Settings {
+			print_llvm: false,
+			opt_level: OptLevel::O0,
+			call_linker: false,
+			input_name: "test.srm".to_string(),
+			object_name: String::new(),
+			target_triple: None,
+			cpu: None,
+			cpu_features: None,
+			emit: Vec::new(),
+		};
+		let (ast, errs) = parse_text(src);
+		assert_eq!(errs, [], "Failed to parse {}", src);
+		let (_, ast) = ast.unwrap();
+		let prog = create_program(&ast, &mut Scope::get_global_scope(), &settings);
+		(prog.data, prog.errors)
+	}
+
+	/// The value of the last expression in the program
+	fn run(prog: &Program) -> Value {
+		Interpreter::new().run_program(prog).unwrap()
+	}
+
+	#[test]
+	fn union_match_missing_a_variant() {
+		let (_, errs) = check("let x: Int | Null = 1\nmatch x { n: Int => n }");
+		assert_eq!(errs.len(), 1);
+		assert_eq!(errs[0].get_description(), "Match doesn't handle 'Null'");
+		assert!(!errs[0].is_warning());
+	}
+
+	#[test]
+	fn enum_match_missing_a_variant() {
+		let src = "enum Shape { Circle(Float), Square(Float) }
+			let s = Shape.Square(2.0)
+			match s { Shape.Circle(r) => r }";
+		let (_, errs) = check(src);
+		assert_eq!(errs.len(), 1);
+		assert_eq!(
+			errs[0].get_description(),
+			"Match doesn't handle 'Shape.Square'"
+		);
+	}
+
+	#[test]
+	fn unreachable_arm_is_a_warning() {
+		let (prog, errs) = check("let x: Int | Null = 1\nmatch x { _ => 1, null => 2 }");
+		assert!(matches!(
+			errs.as_slice(),
+			[CompError::UnreachablePattern(_)]
+		));
+		assert!(errs[0].is_warning());
+		// Warnings don't stop the program from running
+		assert_eq!(run(&prog), Value::Int(1));
+	}
+
+	#[test]
+	fn exhaustive_match_runs() {
+		let (prog, errs) = check("let x: Int | Null = null\nmatch x { n: Int => n, null => 7 }");
+		assert_eq!(errs, []);
+		assert_eq!(run(&prog), Value::Int(7));
+	}
+}
@@ -0,0 +1,776 @@
+use crate::ast1::{ConstantData, Op, Prefix};
+use crate::ast2::*;
+use crate::ast3::*;
+use crate::runner::ImportMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+/// A value produced while walking the typed tree
+/// Objects are shared by reference and primitives are copied, the same as in compiled code
+#[derive(Debug, Clone)]
+pub enum Value {
+	Null,
+	Bool(bool),
+	Int(i32),
+	Float(f32),
+	Char(u8),
+	Str(Rc<str>),
+	Array(Rc<RefCell<Vec<Value>>>),
+	Struct(Rc<RefCell<BTreeMap<String, Value>>>),
+	Func(Rc<FunctionAst>),
+	/// An external function implemented by the interpreter itself
+	Host(String),
+	/// A block of raw memory returned from malloc, only its size in bytes is tracked
+	Ptr(usize),
+	Type(CompType),
+	/// A value of an enum, with the position of its variant and the variant's payload
+	Variant(CompType, usize, Box<Value>),
+	/// A value stored in a union, with the variant of the union it's stored as
+	Union(CompType, Box<Value>),
+}
+
+impl PartialEq for Value {
+	fn eq(&self, other: &Self) -> bool {
+		use Value::*;
+		match (self, other) {
+			(Null, Null) => true,
+			(Bool(a), Bool(b)) => a == b,
+			(Int(a), Int(b)) => a == b,
+			(Float(a), Float(b)) => a == b,
+			(Char(a), Char(b)) => a == b,
+			(Int(a), Char(b)) | (Char(b), Int(a)) => *a == *b as i32,
+			(Str(a), Str(b)) => a == b,
+			(Array(a), Array(b)) => Rc::ptr_eq(a, b),
+			(Struct(a), Struct(b)) => Rc::ptr_eq(a, b),
+			(Func(a), Func(b)) => Rc::ptr_eq(a, b),
+			(Host(a), Host(b)) => a == b,
+			(Type(a), Type(b)) => a == b,
+			(Variant(ty_a, a, payload_a), Variant(ty_b, b, payload_b)) => {
+				ty_a == ty_b && a == b && payload_a == payload_b
+			}
+			(Union(ty_a, a), Union(ty_b, b)) => ty_a == ty_b && a == b,
+			// Like compiled code the other value has to be of the variant being held
+			(Union(ty, a), b) | (b, Union(ty, a)) => ty.super_type_of(&b.get_type()) && **a == *b,
+			_ => false,
+		}
+	}
+}
+
+impl Value {
+	/// The type of the value as it would be seen by `typeof` at runtime
+	pub fn get_type(&self) -> CompType {
+		use Value::*;
+		match self {
+			Null => CompType::Null,
+			Bool(_) => CompType::Bool,
+			Int(_) => CompType::Int,
+			Float(_) => CompType::Float,
+			Char(_) => CompType::Char,
+			Str(str) => CompType::Str(ConstantData::Int(str.len() as i32).to_type().boxed()),
+			Array(elements) => {
+				let elements = elements.borrow();
+				CompType::Array(
+					elements
+						.first()
+						.map(Value::get_type)
+						.unwrap_or(CompType::Unknown)
+						.boxed(),
+					ConstantData::Int(elements.len() as i32).to_type().boxed(),
+				)
+			}
+			Struct(fields) => CompType::Struct(
+				fields
+					.borrow()
+					.iter()
+					.map(|(k, v)| (k.clone(), v.get_type()))
+					.collect(),
+			),
+			Func(func) => func.as_type(),
+			Host(_) => CompType::Unknown,
+			Ptr(_) => CompType::Ptr,
+			Type(_) => CompType::Type,
+			Variant(ty, _, _) => ty.clone(),
+			Union(ty, _) => ty.clone(),
+		}
+	}
+
+	fn as_int(&self) -> Result<i32, String> {
+		match self {
+			Value::Int(int) => Ok(*int),
+			Value::Char(char) => Ok(*char as i32),
+			x => Err(format!("Expected an Int but found '{}'", x)),
+		}
+	}
+
+	fn as_bool(&self) -> Result<bool, String> {
+		match self {
+			Value::Bool(bool) => Ok(*bool),
+			x => Err(format!("Expected a Bool but found '{}'", x)),
+		}
+	}
+}
+
+impl Display for Value {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		use Value::*;
+		match self {
+			Null => write!(f, "null"),
+			Bool(bool) => write!(f, "{}", bool),
+			Int(int) => write!(f, "{}", int),
+			Float(float) => write!(f, "{}", format_g(*float as f64)),
+			Char(char) => write!(f, "{:?}", *char as char),
+			Str(str) => write!(f, "{:?}", str),
+			Array(elements) => write!(
+				f,
+				"[{}]",
+				elements
+					.borrow()
+					.iter()
+					.map(Value::to_string)
+					.collect::<Vec<_>>()
+					.join(", ")
+			),
+			Struct(fields) => write!(
+				f,
+				"{{{}}}",
+				fields
+					.borrow()
+					.iter()
+					.map(|(k, v)| format!("{}: {}", k, v))
+					.collect::<Vec<_>>()
+					.join(", ")
+			),
+			Func(func) => write!(f, "<function {}>", func.as_type()),
+			Host(name) => write!(f, "<external function {}>", name),
+			Ptr(size) => write!(f, "<pointer to {} bytes>", size),
+			Type(ty) => write!(f, "{}", ty),
+			Variant(ty, tag, payload) => {
+				if let CompType::Enum(name, variants) = ty {
+					write!(f, "{}.{}", name, variants[*tag].0)?;
+				}
+				match payload.as_ref() {
+					Struct(fields) if fields.borrow().is_empty() => Ok(()),
+					// Fields given by position are shown the same way they're written
+					Struct(fields) if fields.borrow().contains_key("0") => write!(
+						f,
+						"({})",
+						fields
+							.borrow()
+							.values()
+							.map(Value::to_string)
+							.collect::<Vec<_>>()
+							.join(", ")
+					),
+					payload => write!(f, "({})", payload),
+				}
+			}
+			Union(_, val) => write!(f, "{}", val),
+		}
+	}
+}
+
+/// How execution is leaving the loop or function it's in early
+enum Jump {
+	Break,
+	Continue,
+	Return(Value),
+}
+
+/// Executes a typed program directly without going through LLVM
+pub struct Interpreter {
+	globals: HashMap<String, Value>,
+	frames: Vec<HashMap<String, Value>>,
+	/// Files that have already been run so imports shared between files only run once
+	loaded: Vec<String>,
+	/// Nothing else is evaluated while this is set, until the loop or function it's leaving is reached
+	jump: Option<Jump>,
+	/// The return type of each function being run, so returned values can be stored to match
+	returns: Vec<CompType>,
+}
+
+impl Default for Interpreter {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Interpreter {
+	pub fn new() -> Self {
+		let mut globals = HashMap::new();
+		// malloc is provided by the global scope rather than declared in the standard library
+		globals.insert("malloc".to_string(), Value::Host("malloc".to_string()));
+		Interpreter {
+			globals,
+			frames: Vec::new(),
+			loaded: Vec::new(),
+			jump: None,
+			returns: Vec::new(),
+		}
+	}
+
+	/// Runs the top level of a program, declaring all of its globals
+	pub fn run_program(&mut self, prog: &Program) -> Result<Value, String> {
+		self.allocate_arrays(&prog.body)?;
+		self.evaluate(&prog.body)
+	}
+
+	/// Calls a function in the global scope by name
+	pub fn call_function(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+		let func = self.get_variable(name)?;
+		self.call(&func, name, args)
+	}
+
+	pub fn get_global(&self, name: &str) -> Option<&Value> {
+		self.globals.get(name)
+	}
+
+	/// Runs a file after everything it imports, skipping any that have already been run
+	pub fn load_file(
+		&mut self,
+		name: &str,
+		files: &HashMap<String, ImportMap>,
+	) -> Result<(), String> {
+		if self.loaded.iter().any(|x| x == name) {
+			return Ok(());
+		}
+		self.loaded.push(name.to_string());
+		let file = files
+			.get(name)
+			.ok_or_else(|| format!("Could not find a module at '{}'", name))?;
+		for import in file.depends_on.iter().flatten() {
+			self.load_file(&import.file, files)?;
+		}
+		let prog = file
+			.program
+			.as_ref()
+			.ok_or_else(|| format!("The module '{}' has not been type checked", name))?;
+		self.run_program(prog)?;
+		Ok(())
+	}
+
+	fn current_scope(&mut self) -> &mut HashMap<String, Value> {
+		self.frames.last_mut().unwrap_or(&mut self.globals)
+	}
+
+	fn get_variable(&self, name: &str) -> Result<Value, String> {
+		self.frames
+			.last()
+			.and_then(|frame| frame.get(name))
+			.or_else(|| self.globals.get(name))
+			.cloned()
+			.ok_or_else(|| format!("Tried to load nonexistent variable {}", name))
+	}
+
+	/// Like compiled code every variable written inside a function is local to it
+	fn set_variable(&mut self, name: &str, val: Value) {
+		self.current_scope().insert(name.to_string(), val);
+	}
+
+	/// Array literals are replaced by writes to hidden `.array` variables before we get here
+	/// Like the compiler we give them their memory when entering the function that uses them
+	fn allocate_arrays(&mut self, body: &ExpEnvironment) -> Result<(), String> {
+		for var in body.get_all_written_variables() {
+			if var.get_name().starts_with(".array") {
+				let len = match var.get_type() {
+					CompType::Array(_, len) => match len.as_ref() {
+						CompType::Constant(ConstantData::Int(len)) => *len as usize,
+						_ => return Err("Cannot allocate an array without its length".to_string()),
+					},
+					ty => return Err(format!("Expected an array but found '{}'", ty)),
+				};
+				let arr = Value::Array(Rc::new(RefCell::new(vec![Value::Null; len])));
+				self.current_scope().insert(var.get_name(), arr);
+			}
+		}
+		Ok(())
+	}
+
+	fn call(&mut self, func: &Value, name: &str, args: Vec<Value>) -> Result<Value, String> {
+		let func = match func {
+			Value::Func(func) => func.clone(),
+			Value::Host(name) => return self.call_host(name, args),
+			x => return Err(format!("Cannot call '{}' as a function", x)),
+		};
+		let prog = match &func.body {
+			Some(prog) => prog,
+			None => return self.call_host(name, args),
+		};
+		let frame = func
+			.arguments
+			.iter()
+			.map(CompVariable::get_name)
+			.zip(args)
+			.collect();
+		self.frames.push(frame);
+		self.returns.push(func.return_type.clone());
+		let res = self
+			.allocate_arrays(&prog.body)
+			.and_then(|_| self.evaluate(&prog.body));
+		self.returns.pop();
+		self.frames.pop();
+		let res = match self.jump.take() {
+			Some(Jump::Return(val)) => val,
+			_ => convert_union(res?, &prog.body.result_type, &func.return_type),
+		};
+		Ok(if func.return_type == CompType::Null {
+			Value::Null
+		} else {
+			res
+		})
+	}
+
+	fn call_host(&mut self, name: &str, args: Vec<Value>) -> Result<Value, String> {
+		let mut stdout = std::io::stdout();
+		Ok(match (name, args.as_slice()) {
+			("puts", [Value::Str(str)]) => {
+				writeln!(stdout, "{}", str).map_err(|x| x.to_string())?;
+				Value::Int(0)
+			}
+			("putchar", [char]) => {
+				let char = char.as_int()? as u8;
+				stdout.write_all(&[char]).map_err(|x| x.to_string())?;
+				Value::Int(char as i32)
+			}
+			("getchar", []) => {
+				stdout.flush().map_err(|x| x.to_string())?;
+				let mut buf = [0];
+				match std::io::stdin().read(&mut buf) {
+					Ok(1) => Value::Int(buf[0] as i32),
+					_ => Value::Int(-1),
+				}
+			}
+			("malloc", [size]) => Value::Ptr(size.as_int()? as usize),
+			(name, args) => {
+				return Err(format!(
+					"No host implementation of external function '{}' for arguments {:?}",
+					name, args
+				))
+			}
+		})
+	}
+
+	fn evaluate(&mut self, exp: &ExpEnvironment) -> Result<Value, String> {
+		if self.jump.is_some() {
+			return Ok(Value::Null);
+		}
+		Ok(match exp.expression.as_ref() {
+			CompExpression::Call(callee, _, args) => {
+				let func = self.evaluate(callee)?;
+				let params = match &callee.result_type {
+					CompType::Callible(params, _) => params.clone(),
+					_ => Vec::new(),
+				};
+				let mut values = Vec::new();
+				for (i, arg) in args.iter().enumerate() {
+					let val = self.evaluate(arg)?;
+					// Arguments going into a union parameter have to be stored like one
+					values.push(match params.get(i) {
+						Some(param) => convert_union(val, &arg.result_type, param),
+						None => val,
+					});
+				}
+				let args = values;
+				self.call(&func, &callee.get_name(), args)?
+			}
+			CompExpression::BinOp(op @ (Op::And | Op::Or), left, right) => {
+				// The right side is only run if it can change the result
+				let lhs = self.evaluate(left)?.as_bool()?;
+				if lhs == (op == &Op::Or) {
+					Value::Bool(lhs)
+				} else {
+					Value::Bool(self.evaluate(right)?.as_bool()?)
+				}
+			}
+			CompExpression::BinOp(op, left, right) => {
+				let lhs = self.evaluate(left)?;
+				let rhs = self.evaluate(right)?;
+				eval_bin_op(op, lhs, rhs)?
+			}
+			CompExpression::OneOp(Prefix::Neg, exp) => match self.evaluate(exp)? {
+				Value::Int(int) => Value::Int(int.wrapping_neg()),
+				Value::Float(float) => Value::Float(-float),
+				x => return Err(format!("Cannot negate '{}'", x)),
+			},
+			CompExpression::OneOp(Prefix::Not, exp) => Value::Bool(!self.evaluate(exp)?.as_bool()?),
+			CompExpression::OneOp(Prefix::BitNot, exp) => {
+				Value::Int(!self.evaluate(exp)?.as_int()?)
+			}
+			CompExpression::Read(var) => self.get_variable(&var.get_name())?,
+			CompExpression::Conversion(exp, ty) => {
+				let val = self.evaluate(exp)?;
+				if exp.result_type.is_union() || ty.is_union() {
+					convert_union(val, &exp.result_type, ty)
+				} else {
+					convert(val, ty)?
+				}
+			}
+			CompExpression::Assign(mem, exp) => {
+				let val = match exp.expression.as_ref() {
+					// Stubs for external functions are resolved by the name they're declared with
+					CompExpression::Value(CompData::Func(func)) if func.body.is_none() => {
+						Value::Host(mem.variable.get_name())
+					}
+					_ => self.evaluate(exp)?,
+				};
+				let stored = convert_union(val.clone(), &exp.result_type, &memory_type(mem));
+				self.assign(mem, stored)?;
+				val
+			}
+			CompExpression::Value(val) => get_value(val),
+			CompExpression::IfElse(if_exp) => {
+				// Both branches are stored as the if's type, which can be a union of the two
+				let branch = if self.evaluate(&if_exp.cond)?.as_bool()? {
+					&if_exp.then
+				} else {
+					&if_exp.otherwise
+				};
+				let val = self.evaluate(branch)?;
+				convert_union(val, &branch.result_type, &exp.result_type)
+			}
+			CompExpression::WhileLoop { cond, body } => {
+				while self.evaluate(cond)?.as_bool()? {
+					self.evaluate(body)?;
+					match self.jump {
+						Some(Jump::Break) => {
+							self.jump = None;
+							break;
+						}
+						Some(Jump::Continue) => self.jump = None,
+						Some(Jump::Return(_)) => break,
+						None => {}
+					}
+				}
+				Value::Null
+			}
+			CompExpression::List(expressions) => {
+				let mut last = Value::Null;
+				for x in expressions {
+					last = self.evaluate(x)?;
+				}
+				last
+			}
+			CompExpression::Index(arr, index) => {
+				let arr = self.evaluate(arr)?;
+				let index = self.evaluate(index)?.as_int()?;
+				get_index(&arr, index)?
+			}
+			CompExpression::DotAccess(val, (key, _)) => {
+				let val = self.evaluate(val)?;
+				get_property(&val, key)?
+			}
+			CompExpression::Typeof(exp) => Value::Type(self.evaluate_type(exp)?.widen()),
+			CompExpression::Struct(fields) => {
+				let keys = match &exp.result_type {
+					CompType::Struct(keys) => keys.clone(),
+					_ => BTreeMap::new(),
+				};
+				let mut data = BTreeMap::new();
+				for (key, (_, exp)) in fields {
+					let val = self.evaluate(exp)?;
+					let val = match keys.get(key) {
+						Some(ty) => convert_union(val, &exp.result_type, ty),
+						None => val,
+					};
+					data.insert(key.clone(), val);
+				}
+				Value::Struct(Rc::new(RefCell::new(data)))
+			}
+			CompExpression::Array(elements) => {
+				let elements = elements
+					.iter()
+					.map(|x| self.evaluate(x))
+					.collect::<Result<Vec<_>, _>>()?;
+				Value::Array(Rc::new(RefCell::new(elements)))
+			}
+			CompExpression::Template(parts) => {
+				let mut text = String::new();
+				for part in parts {
+					text += &template_text(&self.evaluate(part)?);
+				}
+				Value::Str(text.into())
+			}
+			CompExpression::Break => {
+				self.jump = Some(Jump::Break);
+				Value::Null
+			}
+			CompExpression::Continue => {
+				self.jump = Some(Jump::Continue);
+				Value::Null
+			}
+			CompExpression::Return(exp) => {
+				let val = self.evaluate(exp)?;
+				let val = match self.returns.last() {
+					Some(return_type) => convert_union(val, &exp.result_type, return_type),
+					None => val,
+				};
+				self.jump = Some(Jump::Return(val));
+				Value::Null
+			}
+			CompExpression::Variant(ty, tag, payload) => {
+				Value::Variant(ty.clone(), *tag, Box::new(self.evaluate(payload)?))
+			}
+			CompExpression::Tag(exp) => match self.evaluate(exp)? {
+				Value::Variant(_, tag, _) => Value::Int(tag as i32),
+				x => return Err(format!("Expected an enum but found '{}'", x)),
+			},
+			CompExpression::Is(exp, ty) => Value::Bool(ty.super_type_of(&self.evaluate_type(exp)?)),
+			CompExpression::Payload(exp, tag) => match self.evaluate(exp)? {
+				Value::Variant(_, current, payload) if current == *tag => *payload,
+				x => return Err(format!("'{}' doesn't hold the expected variant", x)),
+			},
+		})
+	}
+
+	/// The type a value has when it's checked at runtime, which is only known for unions
+	fn evaluate_type(&mut self, exp: &ExpEnvironment) -> Result<CompType, String> {
+		let val = self.evaluate(exp)?;
+		Ok(if exp.result_type.is_union() {
+			val.get_type()
+		} else {
+			exp.result_type.clone()
+		})
+	}
+
+	fn assign(&mut self, mem: &MemoryLocation, val: Value) -> Result<(), String> {
+		let (last, path) = match mem.accessing.split_last() {
+			Some(accesses) => accesses,
+			None => {
+				self.set_variable(&mem.variable.get_name(), val);
+				return Ok(());
+			}
+		};
+		let mut target = self.get_variable(&mem.variable.get_name())?;
+		for (access, _) in path {
+			target = match access {
+				IndexOption::Index(index) => {
+					let index = self.evaluate(index)?.as_int()?;
+					get_index(&target, index)?
+				}
+				IndexOption::Dot(prop) => get_property(&target, prop)?,
+			};
+		}
+		match (&last.0, target) {
+			(IndexOption::Index(index), Value::Array(elements)) => {
+				let index = self.evaluate(index)?.as_int()?;
+				let mut elements = elements.borrow_mut();
+				let len = elements.len();
+				let element = elements.get_mut(index as usize).ok_or_else(|| {
+					format!(
+						"Index {} is out of bounds for array of length {}",
+						index, len
+					)
+				})?;
+				*element = val;
+			}
+			(IndexOption::Dot(prop), Value::Struct(fields)) => {
+				fields.borrow_mut().insert(prop.clone(), val);
+			}
+			(_, x) => return Err(format!("Cannot assign to an element of '{}'", x)),
+		}
+		Ok(())
+	}
+}
+
+/// The type of the variable, element or field being assigned to
+fn memory_type(mem: &MemoryLocation) -> CompType {
+	let mut ty = mem.variable.get_type();
+	for (access, _) in &mem.accessing {
+		ty = match (ty.widen(), access) {
+			(CompType::Array(element, _), IndexOption::Index(_)) => *element,
+			(CompType::Struct(fields), IndexOption::Dot(prop)) => {
+				fields.get(prop).cloned().unwrap_or(CompType::Unknown)
+			}
+			_ => CompType::Unknown,
+		};
+	}
+	ty
+}
+
+/// Changes how a value is kept when it widens into a union, moves between unions or gets
+/// taken out of one after narrowing, the same as compiled code does
+fn convert_union(val: Value, from: &CompType, to: &CompType) -> Value {
+	let (from, to) = (from.widen(), to.widen());
+	if from == to || !(from.is_union() || to.is_union()) {
+		return val;
+	}
+	// The variant of the new union a value of type `ty` gets stored as
+	let variant = |ty: &CompType| {
+		to.get_variants()
+			.into_iter()
+			.find(|x| x.super_type_of(ty))
+			.unwrap_or_else(|| ty.clone())
+	};
+	match val {
+		Value::Union(_, val) if !to.is_union() => *val,
+		Value::Union(ty, val) => Value::Union(variant(&ty), val),
+		val if to.is_union() => Value::Union(variant(&from), Box::new(val)),
+		val => val,
+	}
+}
+
+fn get_value(val: &CompData) -> Value {
+	match val {
+		CompData::Null => Value::Null,
+		CompData::Bool(bool) => Value::Bool(*bool),
+		CompData::Int(int) => Value::Int(*int),
+		CompData::Float(float) => Value::Float(*float),
+		CompData::Str(str) => Value::Str(str.as_str().into()),
+		CompData::Char(char) => Value::Char(*char),
+		CompData::Func(func) => Value::Func(Rc::new(func.clone())),
+	}
+}
+
+fn get_index(arr: &Value, index: i32) -> Result<Value, String> {
+	let element = match arr {
+		Value::Array(elements) => elements.borrow().get(index as usize).cloned(),
+		// Strings are null terminated in compiled code so reading one past the end gives a null char
+		Value::Str(str) => match str.as_bytes().get(index as usize) {
+			Some(char) => Some(Value::Char(*char)),
+			None if index as usize == str.len() => Some(Value::Char(0)),
+			None => None,
+		},
+		x => return Err(format!("Cannot index into '{}'", x)),
+	};
+	element.ok_or_else(|| format!("Index {} is out of bounds for '{}'", index, arr))
+}
+
+/// Formats a float the way printf's `%g` does, which is what compiled templates use
+fn format_g(float: f64) -> String {
+	if !float.is_finite() {
+		let text = if float.is_nan() { "nan" } else { "inf" };
+		return format!("{}{}", if float < 0.0 { "-" } else { "" }, text);
+	}
+	let trim = |x: String| {
+		if x.contains('.') {
+			x.trim_end_matches('0').trim_end_matches('.').to_string()
+		} else {
+			x
+		}
+	};
+	// 6 significant digits, the exponent is the one after rounding to them
+	let sci = format!("{:.5e}", float);
+	let (mantissa, exp) = sci.split_once('e').unwrap();
+	let exp = exp.parse::<i32>().unwrap();
+	if (-4..6).contains(&exp) {
+		trim(format!("{:.*}", (5 - exp) as usize, float))
+	} else {
+		let sign = if exp < 0 { '-' } else { '+' };
+		format!("{}e{}{:02}", trim(mantissa.to_string()), sign, exp.abs())
+	}
+}
+
+/// How a value is written into a template string, without the quotes used when displaying it
+fn template_text(val: &Value) -> String {
+	match val {
+		Value::Str(str) => str.to_string(),
+		Value::Char(char) => (*char as char).to_string(),
+		Value::Union(_, val) => template_text(val),
+		x => x.to_string(),
+	}
+}
+
+fn get_property(val: &Value, key: &str) -> Result<Value, String> {
+	match (val, key) {
+		(Value::Struct(fields), key) => fields
+			.borrow()
+			.get(key)
+			.cloned()
+			.ok_or_else(|| format!("Property '{}' does not exist on '{}'", key, val)),
+		(Value::Array(elements), "length") => Ok(Value::Int(elements.borrow().len() as i32)),
+		(Value::Str(str), "length") => Ok(Value::Int(str.len() as i32)),
+		(x, key) => Err(format!("Cannot access property '{}' of '{}'", key, x)),
+	}
+}
+
+fn convert(val: Value, ty: &CompType) -> Result<Value, String> {
+	Ok(match (val, ty) {
+		(Value::Ptr(size), CompType::IntPtr) => {
+			Value::Array(Rc::new(RefCell::new(vec![Value::Int(0); size / 4])))
+		}
+		(Value::Int(int), CompType::Char) => Value::Char(int as u8),
+		(Value::Char(char), CompType::Int) => Value::Int(char as i32),
+		(Value::Int(int), CompType::Float) => Value::Float(int as f32),
+		(Value::Float(float), CompType::Int) => Value::Int(float as i32),
+		(val, _) => val,
+	})
+}
+
+/// Dividing fails for a zero divisor, and overflows for `i32::MIN` divided by -1
+fn checked_division(a: i32, b: i32, divide: fn(i32, i32) -> Option<i32>) -> Result<i32, String> {
+	if b == 0 {
+		return Err("Attempted to divide by zero".to_string());
+	}
+	divide(a, b).ok_or_else(|| format!("Dividing {} by {} overflows an Int", a, b))
+}
+
+fn eval_bin_op(op: &Op, lhs: Value, rhs: Value) -> Result<Value, String> {
+	use Op::*;
+	use Value::*;
+	Ok(match (op, lhs, rhs) {
+		(Eq, a, b) => Bool(a == b),
+		(Neq, a, b) => Bool(a != b),
+		(Sub, Char(a), Char(b)) => Int(a as i32 - b as i32),
+		(Add | Sub, Char(a), Int(b)) | (Add, Int(b), Char(a)) => {
+			match eval_bin_op(op, Int(a as i32), Int(b))? {
+				Int(x) => Char(x as u8),
+				x => x,
+			}
+		}
+		(op, Char(a), b) => eval_bin_op(op, Int(a as i32), b)?,
+		(op, a, Char(b)) => eval_bin_op(op, a, Int(b as i32))?,
+		(op, Int(a), Int(b)) => match op {
+			Add => Int(a.wrapping_add(b)),
+			Sub => Int(a.wrapping_sub(b)),
+			Mult => Int(a.wrapping_mul(b)),
+			Div => Int(checked_division(a, b, i32::checked_div)?),
+			Mod => Int(checked_division(a, b, i32::checked_rem)?),
+			Lt => Bool(a < b),
+			Gt => Bool(a > b),
+			Le => Bool(a <= b),
+			Ge => Bool(a >= b),
+			BitAnd => Int(a & b),
+			BitOr => Int(a | b),
+			BitXor => Int(a ^ b),
+			Shl => Int(a.wrapping_shl(b as u32)),
+			Shr => Int(a.wrapping_shr(b as u32)),
+			And | Or => return Err(format!("Operator '{}' is not supported for Ints", op)),
+			Eq | Neq => unreachable!(),
+		},
+		(op, Float(a), Float(b)) => match op {
+			Add => Float(a + b),
+			Sub => Float(a - b),
+			Mult => Float(a * b),
+			Div => Float(a / b),
+			Mod => Float(a % b),
+			Lt => Bool(a < b),
+			Gt => Bool(a > b),
+			Le => Bool(a <= b),
+			Ge => Bool(a >= b),
+			Eq | Neq => unreachable!(),
+			op => return Err(format!("Operator '{}' is not supported for Floats", op)),
+		},
+		(Add, Str(a), Str(b)) => Str(format!("{}{}", a, b).into()),
+		(op, a, b) => {
+			return Err(format!(
+				"Operator '{}' is not supported for '{}' and '{}'",
+				op, a, b
+			))
+		}
+	})
+}
+
+/// Runs the file and everything it imports, then calls its main function
+/// Returns the value main returned to be used as an exit code
+pub fn interpret(name: &str, files: &HashMap<String, ImportMap>) -> Result<i32, String> {
+	let mut interpreter = Interpreter::new();
+	interpreter.load_file(name, files)?;
+	let res = interpreter.call_function("main", Vec::new())?;
+	std::io::stdout().flush().map_err(|x| x.to_string())?;
+	match res {
+		Value::Int(code) => Ok(code),
+		_ => Ok(0),
+	}
+}
@@ -1,19 +1,26 @@
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
 use std::ops::Range;
 
-use chumsky::{
-	error::Cheap,
-	prelude::*,
-	text::{ident, whitespace},
-};
+use chumsky::{error::Simple, prelude::*, text::ident};
 #[derive(Clone, PartialEq, Debug)]
 pub enum Token {
 	Null,
 	False,
 	True,
 	Str(String),
+	Char(u8),
 	If,
 	Else,
 	While,
+	Break,
+	Continue,
+	Return,
+	For,
+	Of,
+	Impl,
+	Enum,
+	Match,
 	Ident(String),
 	Int(i32),
 	Colon,
@@ -35,60 +42,303 @@ pub enum Token {
 	As,
 	From,
 	Import,
+	StartTemplate,
+	EndTemplate,
+	/// Text between the interpolated values of a template string
+	TemplateText(String),
+	StartInterpolation,
+	EndInterpolation,
+	/// The text of a `///` or `/** */` comment
+	Doc(String),
 }
 
-fn integer() -> impl Parser<char, i32, Error = Cheap<char>> {
-	text::int(10).map(parse_to_i32)
+// Floats aren't Eq or Hash so these have to be written by hand for chumsky's errors
+impl Eq for Token {}
+impl Hash for Token {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		std::mem::discriminant(self).hash(state);
+		match self {
+			Token::Str(x) | Token::Ident(x) | Token::Operator(x) | Token::Doc(x) => x.hash(state),
+			Token::TemplateText(x) => x.hash(state),
+			Token::Int(x) => x.hash(state),
+			Token::Char(x) => x.hash(state),
+			Token::Float(x) => x.to_bits().hash(state),
+			_ => {}
+		}
+	}
 }
 
-fn float() -> impl Parser<char, f32, Error = Cheap<char>> {
-	(filter::<_, _, Cheap<char>>(char::is_ascii_digit)
-		.repeated()
-		.at_least(1)
+impl Display for Token {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Token::Null => write!(f, "null"),
+			Token::False => write!(f, "false"),
+			Token::True => write!(f, "true"),
+			Token::Str(x) => write!(f, "{:?}", x),
+			Token::Char(x) => write!(f, "{:?}", *x as char),
+			Token::If => write!(f, "if"),
+			Token::Else => write!(f, "else"),
+			Token::While => write!(f, "while"),
+			Token::Break => write!(f, "break"),
+			Token::Continue => write!(f, "continue"),
+			Token::Return => write!(f, "return"),
+			Token::For => write!(f, "for"),
+			Token::Of => write!(f, "of"),
+			Token::Impl => write!(f, "impl"),
+			Token::Enum => write!(f, "enum"),
+			Token::Match => write!(f, "match"),
+			Token::Ident(x) => write!(f, "{}", x),
+			Token::Int(x) => write!(f, "{}", x),
+			Token::Colon => write!(f, ":"),
+			Token::Terminator => write!(f, ";"),
+			Token::Separator => write!(f, ","),
+			Token::Type => write!(f, "type"),
+			Token::Typeof => write!(f, "typeof"),
+			Token::Operator(x) => write!(f, "{}", x),
+			Token::Let => write!(f, "let"),
+			Token::Constant => write!(f, "const"),
+			Token::Exported => write!(f, "extern"),
+			Token::Float(x) => write!(f, "{}", x),
+			Token::StartArray => write!(f, "["),
+			Token::EndArray => write!(f, "]"),
+			Token::StartBlock => write!(f, "{{"),
+			Token::EndBlock => write!(f, "}}"),
+			Token::StartBracket => write!(f, "("),
+			Token::EndBracket => write!(f, ")"),
+			Token::As => write!(f, "as"),
+			Token::From => write!(f, "from"),
+			Token::Import => write!(f, "import"),
+			Token::StartTemplate | Token::EndTemplate => write!(f, "`"),
+			Token::TemplateText(x) => write!(f, "{:?}", x),
+			Token::StartInterpolation => write!(f, "${{"),
+			Token::EndInterpolation => write!(f, "}}"),
+			Token::Doc(_) => write!(f, "doc comment"),
+		}
+	}
+}
+
+/// Marks errors from literals that are written correctly but whose value can't be used
+pub const INVALID_LITERAL: &str = "literal";
+
+/// An error about a literal's value, like a number too large for its type
+pub fn literal_error<T: Hash + Eq>(span: Range<usize>, msg: &str) -> Simple<T> {
+	Simple::custom(span, msg).with_label(INVALID_LITERAL)
+}
+
+/// Digits in the given base, which can be separated by underscores
+fn digits(radix: u32) -> impl Parser<char, String, Error = Simple<char>> + Clone {
+	filter(move |c: &char| c.is_digit(radix))
+		.chain(filter(move |c: &char| c.is_digit(radix) || *c == '_').repeated())
 		.collect::<String>()
-		.then_ignore(just('.'))
-		.then(
-			filter::<_, _, Cheap<char>>(char::is_ascii_digit)
-				.repeated()
-				.at_least(1)
-				.collect::<String>(),
-		))
-	.map(|x| format!("{}.{}", x.0, x.1))
-	.map(parse_to_f32)
+		.map(|x| x.replace('_', ""))
 }
 
-fn parse_to_i32(x: String) -> i32 {
-	x.parse::<i32>().unwrap()
+/// The digits of an integer with its base and whether it has a float suffix
+fn integer() -> impl Parser<char, (String, u32, bool), Error = Simple<char>> + Clone {
+	let prefixed = just("0x")
+		.ignore_then(digits(16))
+		.map(|x| (x, 16))
+		.or(just("0b").ignore_then(digits(2)).map(|x| (x, 2)))
+		.or(just("0o").ignore_then(digits(8)).map(|x| (x, 8)));
+	prefixed
+		.or(digits(10).map(|x| (x, 10)))
+		.then(just("i32").or(just("f32")).or_not())
+		.map(|((digits, radix), suffix)| (digits, radix, suffix == Some("f32")))
 }
 
-fn parse_to_f32(x: String) -> f32 {
-	x.parse::<f32>().unwrap()
+fn float() -> impl Parser<char, String, Error = Simple<char>> + Clone {
+	let exponent = one_of("eE")
+		.ignore_then(one_of("+-").or_not())
+		.then(digits(10))
+		.map(|(sign, x)| format!("e{}{}", sign.unwrap_or('+'), x));
+	digits(10)
+		.then_ignore(just('.'))
+		.then(digits(10))
+		.map(|(whole, fraction)| format!("{}.{}", whole, fraction))
+		.then(exponent.clone().or_not())
+		.map(|(x, exp)| x + &exp.unwrap_or_default())
+		.or(digits(10).then(exponent).map(|(x, exp)| x + &exp))
+		.then_ignore(just("f32").or_not())
 }
 
-fn string() -> impl Parser<char, String, Error = Cheap<char>> {
-	let escape = just('\\').ignore_then(
+/// Also reported by the parser for `2147483648` without a `-` in front
+pub const TOO_LARGE_INT: &str = "Integer literal is too large to fit in type 'Int'";
+
+/// Checks the literal fits in its type once it's known which kind of number it is
+fn number() -> impl Parser<char, Token, Error = Simple<char>> + Clone {
+	float().map(|x| (x, 10, true)).or(integer()).validate(
+		|(digits, radix, is_float), span, emit| {
+			if !is_float {
+				return match i32::from_str_radix(&digits, radix) {
+					Ok(x) => Token::Int(x),
+					// Only fits in an Int once it's negated, which the parser checks for
+					Err(_) if i64::from_str_radix(&digits, radix) == Ok(1 << 31) => {
+						Token::Int(i32::MIN)
+					}
+					Err(_) => {
+						emit(literal_error(span, TOO_LARGE_INT));
+						Token::Int(0)
+					}
+				};
+			}
+			if radix != 10 {
+				emit(literal_error(
+					span.clone(),
+					"Float literals can't have a base prefix",
+				));
+			}
+			let value = digits.parse::<f32>().unwrap_or(f32::INFINITY);
+			if value.is_infinite() {
+				emit(literal_error(
+					span,
+					"Float literal is too large to fit in type 'Float'",
+				));
+			}
+			Token::Float(value)
+		},
+	)
+}
+
+fn escape() -> impl Parser<char, char, Error = Simple<char>> + Clone {
+	just('\\').ignore_then(
 		just('\\')
 			.or(just('/'))
 			.or(just('"'))
+			.or(just('\''))
 			.or(just('b').to('\x08'))
 			.or(just('f').to('\x0C'))
 			.or(just('n').to('\n'))
 			.or(just('r').to('\r'))
 			.or(just('t').to('\t'))
 			.or(just('0').to('\0')),
-	);
+	)
+}
 
+fn string() -> impl Parser<char, String, Error = Simple<char>> + Clone {
 	just('"')
-		.ignore_then(filter(|c| *c != '\\' && *c != '"').or(escape).repeated())
+		.ignore_then(filter(|c| *c != '\\' && *c != '"').or(escape()).repeated())
 		.then_ignore(just('"'))
 		.collect::<String>()
 }
 
-pub fn lexer() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Cheap<char>> {
-	string()
-		.map(Token::Str)
-		.or(float().map(Token::Float))
-		.or(integer().map(Token::Int))
+/// A single character between quotes, which has to fit in a byte
+fn character() -> impl Parser<char, Token, Error = Simple<char>> + Clone {
+	just('\'')
+		.ignore_then(filter(|c| *c != '\\' && *c != '\'').or(escape()))
+		.then_ignore(just('\''))
+		.validate(|c, span, emit| {
+			if !c.is_ascii() {
+				emit(literal_error(
+					span,
+					&format!("Character literal '{}' doesn't fit in type 'Char'", c),
+				));
+			}
+			Token::Char(c as u8)
+		})
+}
+
+/// The text after `//` up to the end of the line
+fn line_comment() -> impl Parser<char, String, Error = Simple<char>> + Clone {
+	just("//").ignore_then(none_of('\n').repeated().collect::<String>())
+}
+
+/// The text between `/*` and `*/`, block comments can be nested
+fn block_comment() -> impl Parser<char, String, Error = Simple<char>> + Clone {
+	recursive(|block| {
+		just("/*")
+			.ignore_then(
+				block
+					.map(|inner| format!("/*{}*/", inner))
+					.or(none_of('*').map(String::from))
+					.or(just('*')
+						.then_ignore(none_of('/').rewind())
+						.map(String::from))
+					.repeated(),
+			)
+			.then_ignore(just("*/"))
+			.map(|parts: Vec<String>| parts.concat())
+	})
+}
+
+// Like rust, `////` and `/***` start normal comments rather than doc comments
+fn is_line_doc(text: &str) -> bool {
+	text.starts_with('/') && !text.starts_with("//")
+}
+
+fn is_block_doc(text: &str) -> bool {
+	text.starts_with('*') && !text.starts_with("**") && text != "*"
+}
+
+/// Doc comments are kept as tokens so they can be attached to declarations
+fn doc_comment() -> impl Parser<char, Token, Error = Simple<char>> + Clone {
+	line_comment()
+		.try_map(|text, span| match is_line_doc(&text) {
+			true => Ok(text[1..].trim().to_string()),
+			false => Err(Simple::expected_input_found(span, Vec::new(), None)),
+		})
+		.or(
+			block_comment().try_map(|text, span| match is_block_doc(&text) {
+				true => Ok(text[1..]
+					.lines()
+					.map(|line| line.trim().trim_start_matches('*').trim())
+					.collect::<Vec<_>>()
+					.join("\n")
+					.trim()
+					.to_string()),
+				false => Err(Simple::expected_input_found(span, Vec::new(), None)),
+			}),
+		)
+		.map(Token::Doc)
+}
+
+/// Whitespace and any comments that aren't doc comments
+fn skipped() -> impl Parser<char, (), Error = Simple<char>> + Clone {
+	let comment = line_comment()
+		.try_map(|text, span| match is_line_doc(&text) {
+			true => Err(Simple::expected_input_found(span, Vec::new(), None)),
+			false => Ok(()),
+		})
+		.or(
+			block_comment().try_map(|text, span| match is_block_doc(&text) {
+				true => Err(Simple::expected_input_found(span, Vec::new(), None)),
+				false => Ok(()),
+			}),
+		);
+	filter(|c: &char| c.is_whitespace())
+		.ignored()
+		.or(comment)
+		.repeated()
+		.ignored()
+}
+
+/// Operators made of two characters are tried first so `<=` isn't read as `<` then `=`
+fn operator() -> impl Parser<char, String, Error = Simple<char>> + Clone {
+	just("=>")
+		.or(just("=="))
+		.or(just("!="))
+		.or(just("<="))
+		.or(just(">="))
+		.or(just("&&"))
+		.or(just("||"))
+		.or(just("<<"))
+		.or(just(">>"))
+		.or(just("++"))
+		.or(just("--"))
+		.or(just("+="))
+		.or(just("-="))
+		.or(just("*="))
+		.or(just("/="))
+		.or(just("%="))
+		.map(String::from)
+		.or(one_of("+-*/%=!<>.|&^~?").map(String::from))
+}
+
+/// Any token other than the braces around blocks, which have to be balanced inside templates
+fn token() -> impl Parser<char, Token, Error = Simple<char>> + Clone {
+	doc_comment()
+		.or(string().map(Token::Str))
+		.or(character())
+		.or(number())
 		.or(ident().map(String::from).map(|x| match x.as_str() {
 			"import" => Token::Import,
 			"from" => Token::From,
@@ -101,28 +351,170 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Cheap<ch
 			"type" => Token::Type,
 			"typeof" => Token::Typeof,
 			"while" => Token::While,
+			"break" => Token::Break,
+			"continue" => Token::Continue,
+			"return" => Token::Return,
+			"for" => Token::For,
+			"of" => Token::Of,
+			"impl" => Token::Impl,
+			"enum" => Token::Enum,
+			"match" => Token::Match,
 			"let" => Token::Let,
 			"const" => Token::Constant,
 			"extern" => Token::Exported,
 			_ => Token::Ident(x),
 		}))
-		.or(one_of("+-*/=!<>.|")
-			.repeated()
-			.at_least(1)
-			.collect::<String>()
-			.map(Token::Operator))
+		.or(operator().map(Token::Operator))
 		.or(just(';').to(Token::Terminator))
 		.or(just(',').to(Token::Separator))
 		.or(just('(').to(Token::StartBracket))
 		.or(just(')').to(Token::EndBracket))
 		.or(just('[').to(Token::StartArray))
 		.or(just(']').to(Token::EndArray))
-		.or(just('{').to(Token::StartBlock))
-		.or(just('}').to(Token::EndBlock))
 		.or(just(":").to(Token::Colon))
-		.padded()
-		.map_with_span(|token, span| (token, span))
+}
+
+fn spanned(
+	token: impl Parser<char, Token, Error = Simple<char>> + Clone,
+) -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Simple<char>> + Clone {
+	token.map_with_span(|token, span| vec![(token, span)])
+}
+
+fn concat<T>(((mut start, middle), end): ((Vec<T>, Vec<T>), Vec<T>)) -> Vec<T> {
+	start.extend(middle);
+	start.extend(end);
+	start
+}
+
+/// A template string split into its text and the tokens of each interpolated expression
+fn template() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Simple<char>> + Clone {
+	recursive(|template| {
+		let block = recursive(|block| {
+			spanned(just('{').to(Token::StartBlock))
+				.then(
+					template
+						.clone()
+						.or(spanned(token()))
+						.or(block)
+						.padded_by(skipped())
+						.repeated()
+						.flatten(),
+				)
+				.then(spanned(just('}').to(Token::EndBlock)))
+				.map(concat)
+		});
+		let interpolation = spanned(just("${").to(Token::StartInterpolation))
+			.then(
+				template
+					.or(spanned(token()))
+					.or(block)
+					.padded_by(skipped())
+					.repeated()
+					.flatten(),
+			)
+			.then(spanned(just('}').to(Token::EndInterpolation)))
+			.map(concat);
+		let text = none_of("`\\$")
+			.or(escape())
+			.or(just('\\').ignore_then(one_of("`$")))
+			.or(just('$').then_ignore(none_of('{').rewind()))
+			.repeated()
+			.at_least(1)
+			.collect::<String>()
+			.map(Token::TemplateText);
+		spanned(just('`').to(Token::StartTemplate))
+			.then(spanned(text).or(interpolation).repeated().flatten())
+			.then(spanned(just('`').to(Token::EndTemplate)))
+			.map(concat)
+	})
+}
+
+pub fn lexer() -> impl Parser<char, Vec<(Token, Range<usize>)>, Error = Simple<char>> {
+	template()
+		.or(spanned(
+			token()
+				.or(just('{').to(Token::StartBlock))
+				.or(just('}').to(Token::EndBlock)),
+		))
+		// Characters that don't start any token are reported then skipped
+		.recover_with(skip_then_retry_until([]))
+		.padded_by(skipped())
 		.repeated()
-		.then_ignore(whitespace())
+		.flatten()
+		.then_ignore(skipped())
 		.then_ignore(end())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn lex(src: &str) -> Vec<Token> {
+		let tokens = lexer().parse(src).expect("Failed to lex");
+		tokens.into_iter().map(|(token, _)| token).collect()
+	}
+
+	/// Lexing has to fail with an error about the literal's value rather than its syntax
+	fn assert_invalid_literal(src: &str) {
+		let (_, errs) = lexer().parse_recovery(src);
+		assert_eq!(errs.len(), 1, "Expected one error for {}", src);
+		assert_eq!(errs[0].label(), Some(INVALID_LITERAL), "{}", src);
+	}
+
+	#[test]
+	fn integer_literals() {
+		assert_eq!(
+			lex("42 1_000 0x1F 0b101 0o17 7i32"),
+			[42, 1000, 31, 5, 15, 7].map(Token::Int)
+		);
+	}
+
+	#[test]
+	fn float_literals() {
+		assert_eq!(
+			lex("1.5 2e3 2.5E-1 3f32"),
+			[1.5, 2000.0, 0.25, 3.0].map(Token::Float)
+		);
+	}
+
+	#[test]
+	fn out_of_range_numbers() {
+		// Fits once it's negated, so the parser decides if it's allowed
+		assert_eq!(lex("2147483648"), [Token::Int(i32::MIN)]);
+		assert_invalid_literal("2147483649");
+		assert_invalid_literal("0x1_0000_0000");
+		assert_invalid_literal("1e39");
+		assert_invalid_literal("0b1f32");
+	}
+
+	#[test]
+	fn char_literals() {
+		let chars = "a\n'".bytes().map(Token::Char).collect::<Vec<_>>();
+		assert_eq!(lex(r"'a' '\n' '\''"), chars);
+		assert_invalid_literal("'é'");
+	}
+
+	#[test]
+	fn template_literals() {
+		assert_eq!(
+			lex("`a ${b} c`"),
+			[
+				Token::StartTemplate,
+				Token::TemplateText("a ".to_string()),
+				Token::StartInterpolation,
+				Token::Ident("b".to_string()),
+				Token::EndInterpolation,
+				Token::TemplateText(" c".to_string()),
+				Token::EndTemplate,
+			]
+		);
+	}
+
+	#[test]
+	fn doc_comments() {
+		assert_eq!(
+			lex("/// A doc\n// Not a doc\n//// Not one either\nlet"),
+			[Token::Doc("A doc".to_string()), Token::Let]
+		);
+	}
+}
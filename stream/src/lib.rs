@@ -4,6 +4,7 @@ pub mod ast2;
 pub mod ast3;
 pub mod compile;
 pub mod errors;
+pub mod interpret;
 pub mod lexer;
 pub mod macros;
 pub mod parser;
@@ -1,39 +1,45 @@
-use crate::{ast1::*, lexer::Token};
-use chumsky::{error::Cheap, prelude::*, recursive::Recursive};
+use crate::{
+	ast1::*,
+	lexer::{literal_error, Token, TOO_LARGE_INT},
+};
+use chumsky::{error::Simple, prelude::*, recursive::Recursive};
 use std::ops::Range;
 
-fn raw_data_parser() -> impl Parser<Token, RawData, Error = Cheap<Token>> {
+fn raw_data_parser() -> impl Parser<Token, RawData, Error = Simple<Token>> {
 	filter_map(|e, x| match x {
 		Token::Str(x) => Ok(RawData::Str(x)),
+		Token::Char(x) => Ok(RawData::Char(x)),
 		Token::Float(x) => Ok(RawData::Float(x)),
 		Token::Int(x) => Ok(RawData::Int(x)),
 		Token::Null => Ok(RawData::Null),
 		Token::True => Ok(RawData::Bool(true)),
 		Token::False => Ok(RawData::Bool(false)),
-		_ => Err(Cheap::expected_input_found(e, Vec::new(), None)),
+		_ => Err(Simple::expected_input_found(e, Vec::new(), Some(x))),
 	})
 }
 
-fn constant_data_parser() -> impl Parser<Token, ConstantData, Error = Cheap<Token>> {
+fn constant_data_parser() -> impl Parser<Token, ConstantData, Error = Simple<Token>> {
 	filter_map(|e, x| match x {
 		Token::Str(x) => Ok(ConstantData::Str(x)),
+		Token::Char(x) => Ok(ConstantData::Char(x)),
 		Token::Float(x) => Ok(ConstantData::Float(x)),
+		Token::Int(i32::MIN) => Err(literal_error(e, TOO_LARGE_INT)),
 		Token::Int(x) => Ok(ConstantData::Int(x)),
 		Token::Null => Ok(ConstantData::Null),
 		Token::True => Ok(ConstantData::Bool(true)),
 		Token::False => Ok(ConstantData::Bool(false)),
-		_ => Err(Cheap::expected_input_found(e, Vec::new(), None)),
+		_ => Err(Simple::expected_input_found(e, Vec::new(), Some(x))),
 	})
 }
 
-fn symbol_parser() -> impl Parser<Token, Symbol, Error = Cheap<Token>> {
+fn symbol_parser() -> impl Parser<Token, Symbol, Error = Simple<Token>> {
 	raw_data_parser()
 		.map(Symbol::Data)
 		.or(token_ident().map(String::from).map(Symbol::Identifier))
 		.labelled("Symbol")
 }
 
-fn type_parser() -> impl Parser<Token, CustomType, Error = Cheap<Token>> {
+fn type_parser() -> impl Parser<Token, CustomType, Error = Simple<Token>> {
 	recursive(|ty: Recursive<Token, CustomType, _>| {
 		let constant = constant_data_parser().map(CustomType::Constant).boxed();
 
@@ -103,39 +109,226 @@ fn type_parser() -> impl Parser<Token, CustomType, Error = Cheap<Token>> {
 	})
 }
 
-fn op_parser(op: Op) -> impl Parser<Token, Op, Error = Cheap<Token>> + 'static {
+fn pattern_parser() -> impl Parser<Token, SpannedPattern, Error = Simple<Token>> {
+	recursive(|pattern: Recursive<Token, SpannedPattern, _>| {
+		let positional = pattern
+			.clone()
+			.separated_by(separator())
+			.delimited_by(Token::StartBracket, Token::EndBracket)
+			.map(|fields| {
+				fields
+					.into_iter()
+					.enumerate()
+					.map(|(i, field)| (i.to_string(), field))
+					.collect()
+			});
+
+		// `{w, h: height}` binds `w` to a variable of the same name
+		let named = token_ident()
+			.map_with_span(|name, span| (name, span))
+			.then(just(Token::Colon).ignore_then(pattern).or_not())
+			.map(|((name, span), field)| {
+				let field = field.unwrap_or_else(|| (span, Pattern::Binding(name.clone())));
+				(name, field)
+			})
+			.separated_by(separator())
+			.allow_trailing()
+			.delimited_by(Token::StartBlock, Token::EndBlock);
+
+		let variant = token_ident()
+			.then_ignore(just(Token::Operator(".".to_string())))
+			.then(token_ident().map_with_span(|name, span| (name, span)))
+			.then(positional.or(named).or_not().map(Option::unwrap_or_default))
+			.map(|((ty, variant), fields)| Pattern::Variant(ty, variant, fields));
+
+		// `n: Int` is used to tell the variants of a union apart
+		let typed = token_ident()
+			.then_ignore(just(Token::Colon))
+			.then(type_parser())
+			.map(|(name, ty)| Pattern::Typed((name != "_").then_some(name), ty));
+
+		let symbol = symbol_parser()
+			.validate(|symbol, span, emit| {
+				// Patterns can't be negated, so this is always 2147483648
+				if matches!(symbol, Symbol::Data(RawData::Int(i32::MIN))) {
+					emit(literal_error(span, TOO_LARGE_INT));
+				}
+				symbol
+			})
+			.map(|symbol| match symbol {
+				Symbol::Identifier(name) if name == "_" => Pattern::Wildcard,
+				Symbol::Identifier(name) => Pattern::Binding(name),
+				Symbol::Data(data) => Pattern::Literal(data),
+			});
+
+		variant
+			.or(typed)
+			.or(symbol)
+			.map_with_span(|pattern, span| (span, pattern))
+			.labelled("Pattern")
+	})
+}
+
+fn op_parser(op: Op) -> impl Parser<Token, Op, Error = Simple<Token>> + Clone + 'static {
 	just(Token::Operator(op.get_str())).to(op)
 }
 
-fn token_ident() -> impl Parser<Token, String, Error = Cheap<Token>> {
+/// Left associative operations between operands parsed by `operand`, all with the same precedence
+fn bin_op_parser<'a>(
+	operand: impl Parser<Token, SpannedExpression, Error = Simple<Token>> + Clone + 'a,
+	op: impl Parser<Token, Op, Error = Simple<Token>> + Clone + 'a,
+) -> impl Parser<Token, SpannedExpression, Error = Simple<Token>> + Clone + 'a {
+	operand
+		.clone()
+		.then(op.then(operand).repeated())
+		.foldl(|left, (op, right)| {
+			(
+				left.0.start..right.0.end,
+				Expression::BinOp(op, Box::new(left), Box::new(right)),
+			)
+		})
+}
+
+fn token_ident() -> impl Parser<Token, String, Error = Simple<Token>> {
 	filter_map(|e, t| match t {
 		Token::Ident(str) => Ok(str),
-		_ => Err(Cheap::expected_input_found(e, Vec::new(), Some(t))),
+		_ => Err(Simple::expected_input_found(e, Vec::new(), Some(t))),
 	})
 }
 
-fn as_name() -> impl Parser<Token, Option<String>, Error = Cheap<Token>> {
+fn as_name() -> impl Parser<Token, Option<String>, Error = Simple<Token>> {
 	just(Token::As).ignore_then(token_ident()).or_not()
 }
 
-fn separator() -> impl Parser<Token, Token, Error = Cheap<Token>> {
+fn separator() -> impl Parser<Token, Token, Error = Simple<Token>> {
 	just(Token::Separator)
 }
 
-fn token_str() -> impl Parser<Token, String, Error = Cheap<Token>> {
+fn token_str() -> impl Parser<Token, String, Error = Simple<Token>> {
 	filter_map(|e, t| match t {
 		Token::Str(str) => Ok(str),
-		_ => Err(Cheap::expected_input_found(e, Vec::new(), Some(t))),
+		_ => Err(Simple::expected_input_found(e, Vec::new(), Some(t))),
 	})
 }
 
-fn exp_parser<'a>() -> impl Parser<Token, (Range<usize>, Expression), Error = Cheap<Token>> + 'a {
+/// Consecutive doc comments joined into one
+fn doc_comments() -> impl Parser<Token, Option<String>, Error = Simple<Token>> + Clone {
+	filter_map(|e, t| match t {
+		Token::Doc(text) => Ok(text),
+		_ => Err(Simple::expected_input_found(e, Vec::new(), Some(t))),
+	})
+	.repeated()
+	.map(|docs| (!docs.is_empty()).then(|| docs.join("\n")))
+}
+
+/// Drops doc comments that aren't written before a declaration or method, like ones on struct
+/// fields or arguments, so they're skipped like normal comments instead of being a syntax error
+pub fn skip_stray_docs(tokens: Vec<(Token, Range<usize>)>) -> Vec<(Token, Range<usize>)> {
+	let mut kept = Vec::new();
+	let mut depth = 0;
+	// The depth of the block holding the methods of each impl being read
+	let mut impl_bodies = Vec::new();
+	let mut in_impl_head = false;
+	for (i, (token, span)) in tokens.iter().enumerate() {
+		match token {
+			Token::Doc(_) => {
+				// A run of doc comments documents whatever follows the last one
+				let next = tokens[i..]
+					.iter()
+					.find(|(x, _)| !matches!(x, Token::Doc(_)));
+				let documents = match next.map(|x| &x.0) {
+					Some(
+						Token::Let
+						| Token::Constant
+						| Token::Exported
+						| Token::Type
+						| Token::Enum
+						| Token::Impl,
+					) => true,
+					Some(Token::Ident(_)) => impl_bodies.last() == Some(&depth),
+					_ => false,
+				};
+				if !documents {
+					continue;
+				}
+			}
+			Token::Impl => in_impl_head = true,
+			Token::StartBlock => {
+				depth += 1;
+				if in_impl_head {
+					impl_bodies.push(depth);
+					in_impl_head = false;
+				}
+			}
+			Token::EndBlock => {
+				if impl_bodies.last() == Some(&depth) {
+					impl_bodies.pop();
+				}
+				depth -= 1;
+			}
+			_ => {}
+		}
+		kept.push((token.clone(), span.clone()));
+	}
+	kept
+}
+
+/// Gives a declaration the doc comments written before it, they are dropped from anything else
+fn attach_doc((span, exp): SpannedExpression, doc: Option<String>) -> SpannedExpression {
+	use Expression::*;
+	let exp = match exp {
+		InitAssign(external, constant, name, ty, value, _) => {
+			InitAssign(external, constant, name, ty, value, doc)
+		}
+		TypeDeclaration(name, generics, ty, _) => TypeDeclaration(name, generics, ty, doc),
+		EnumDeclaration(name, variants, _) => EnumDeclaration(name, variants, doc),
+		// Methods without docs of their own are described by the ones on the impl
+		Impl(ty, generics, methods) => Impl(
+			ty,
+			generics,
+			methods
+				.into_iter()
+				.map(|(name, func, own)| (name, func, own.or_else(|| doc.clone())))
+				.collect(),
+		),
+		x => x,
+	};
+	(span, exp)
+}
+
+/// Parses a list of expressions, skipping to the end of any broken ones so the rest can still be checked
+fn statements<'a>(
+	exp: impl Parser<Token, SpannedExpression, Error = Simple<Token>> + Clone + 'a,
+) -> impl Parser<Token, Vec<SpannedExpression>, Error = Simple<Token>> + Clone + 'a {
+	doc_comments()
+		.then(none_of([Token::EndBlock]))
+		.rewind()
+		.ignore_then(doc_comments())
+		.then(
+			exp.recover_with(skip_until([Token::Terminator, Token::EndBlock], |span| {
+				(span, Expression::Invalid)
+			})),
+		)
+		.map(|(doc, exp)| attach_doc(exp, doc))
+		.then_ignore(just(Token::Terminator).or_not())
+		.repeated()
+		.then_ignore(doc_comments())
+}
+
+fn exp_parser<'a>() -> impl Parser<Token, SpannedExpression, Error = Simple<Token>> + Clone + 'a {
 	use Expression::*;
 	recursive(|exp: Recursive<'_, Token, SpannedExpression, _>| {
-		let block_exp = exp
-			.clone()
-			.repeated()
+		let block_exp = statements(exp.clone())
 			.delimited_by(Token::StartBlock, Token::EndBlock)
+			.recover_with(nested_delimiters(
+				Token::StartBlock,
+				Token::EndBlock,
+				[
+					(Token::StartBracket, Token::EndBracket),
+					(Token::StartArray, Token::EndArray),
+				],
+				|span| vec![(span, Invalid)],
+			))
 			.map_with_span(|x, s| (s, Expression::Block(x)))
 			.then_ignore(just(Token::Terminator).or_not())
 			.labelled("Block");
@@ -144,6 +337,15 @@ fn exp_parser<'a>() -> impl Parser<Token, (Range<usize>, Expression), Error = Ch
 			.clone()
 			.separated_by(separator())
 			.delimited_by(Token::StartArray, Token::EndArray)
+			.recover_with(nested_delimiters(
+				Token::StartArray,
+				Token::EndArray,
+				[
+					(Token::StartBracket, Token::EndBracket),
+					(Token::StartBlock, Token::EndBlock),
+				],
+				|span| vec![(span, Invalid)],
+			))
 			.map_with_span(|x, s| (s, Array(x)));
 
 		let generics = token_ident()
@@ -158,7 +360,7 @@ fn exp_parser<'a>() -> impl Parser<Token, (Range<usize>, Expression), Error = Ch
 			.map(Option::unwrap_or_default)
 			.boxed();
 
-		let func_declaration = generics
+		let function = generics
 			.clone()
 			.then(
 				token_ident()
@@ -187,11 +389,31 @@ fn exp_parser<'a>() -> impl Parser<Token, (Range<usize>, Expression), Error = Ch
 				body,
 				return_type: ret,
 			})
+			.boxed();
+
+		let func_declaration = function
+			.clone()
 			.map(RawData::Func)
 			.map(Symbol::Data)
 			.labelled("Function");
 
+		let call_args = exp
+			.clone()
+			.separated_by(separator())
+			.delimited_by(Token::StartBracket, Token::EndBracket)
+			.recover_with(nested_delimiters(
+				Token::StartBracket,
+				Token::EndBracket,
+				[
+					(Token::StartBlock, Token::EndBlock),
+					(Token::StartArray, Token::EndArray),
+				],
+				|span| vec![(span, Invalid)],
+			))
+			.boxed();
+
 		let func_call = token_ident()
+			.map_with_span(|name, s| (s, Terminal(Symbol::Identifier(name))))
 			.then(
 				type_parser()
 					.separated_by(separator())
@@ -203,12 +425,10 @@ fn exp_parser<'a>() -> impl Parser<Token, (Range<usize>, Expression), Error = Ch
 					.or_not()
 					.map(Option::unwrap_or_default),
 			)
-			.then(
-				exp.clone()
-					.separated_by(separator())
-					.delimited_by(Token::StartBracket, Token::EndBracket),
-			)
-			.map_with_span(|((name, generics), args), span| (span, FuncCall(name, generics, args)))
+			.then(call_args.clone())
+			.map_with_span(|((callee, generics), args), span| {
+				(span, FuncCall(Box::new(callee), generics, args))
+			})
 			.labelled("Call");
 
 		let struct_exp = token_ident()
@@ -220,7 +440,22 @@ fn exp_parser<'a>() -> impl Parser<Token, (Range<usize>, Expression), Error = Ch
 			.delimited_by(Token::StartBlock, Token::EndBlock)
 			.map_with_span(|x, s| (s, Struct(x)));
 
+		let template_text = filter_map(|e, t| match t {
+			Token::TemplateText(text) => Ok(Terminal(Symbol::Data(RawData::Str(text)))),
+			_ => Err(Simple::expected_input_found(e, Vec::new(), Some(t))),
+		});
+		let template = template_text
+			.map_with_span(|x, s| (s, x))
+			.or(exp
+				.clone()
+				.delimited_by(Token::StartInterpolation, Token::EndInterpolation))
+			.repeated()
+			.delimited_by(Token::StartTemplate, Token::EndTemplate)
+			.map_with_span(|x, s| (s, Template(x)))
+			.labelled("Template");
+
 		let primary_exp = func_call
+			.or(template)
 			.or(symbol_parser().map_with_span(|x, s| (s, Terminal(x))))
 			.or(exp
 				.clone()
@@ -245,6 +480,7 @@ fn exp_parser<'a>() -> impl Parser<Token, (Range<usize>, Expression), Error = Ch
 					.or(just(Token::Operator(".".to_string()))
 						.ignore_then(token_ident().map_with_span(|k, s| (k, s)))
 						.map_with_span(|x, s| (s, Access::Dot(x.0, x.1))))
+					.or(call_args.map_with_span(|x, s| (s, Access::Call(x))))
 					.repeated(),
 			)
 			.map(|(l, t)| {
@@ -255,6 +491,7 @@ fn exp_parser<'a>() -> impl Parser<Token, (Range<usize>, Expression), Error = Ch
 						match index.1 {
 							Index(index) => Expression::Index(Box::new(arr), Box::new(index)),
 							Dot(prop, s) => DotAccess(Box::new(arr), (prop, s)),
+							Call(args) => FuncCall(Box::new(arr), Vec::new(), args),
 						},
 					)
 				})
@@ -264,91 +501,244 @@ fn exp_parser<'a>() -> impl Parser<Token, (Range<usize>, Expression), Error = Ch
 		enum Access {
 			Index(SpannedExpression),
 			Dot(String, Range<usize>),
+			Call(Vec<SpannedExpression>),
 		}
 
-		let mult_parser = (index_parser.clone())
-			.then(
-				(op_parser(Op::Mult).or(op_parser(Op::Div)))
-					.then(index_parser.clone())
-					.repeated(),
-			)
-			.map(|(l, t)| {
-				t.into_iter().fold(l, |left, (op, right)| {
-					(
-						left.0.start..right.0.end,
-						Expression::BinOp(op, Box::new(left), Box::new(right)),
-					)
-				})
-			})
-			.boxed();
+		let increment = just(Token::Operator("++".to_string()))
+			.to(Op::Add)
+			.or(just(Token::Operator("--".to_string())).to(Op::Sub));
 
-		let add_sub_parser = (mult_parser.clone())
-			.then(
-				(op_parser(Op::Add).or(op_parser(Op::Sub)))
-					.then(mult_parser.clone())
-					.repeated(),
+		// Adding or subtracting one from the target, giving back the old value if it's postfix
+		let step = |op, target: SpannedExpression, postfix, span: Range<usize>| {
+			let one = (span.clone(), Terminal(Symbol::Data(RawData::Int(1))));
+			(
+				span,
+				CompoundAssign(op, Box::new(target), Box::new(one), postfix),
 			)
-			.map(|x| {
-				x.1.into_iter().fold(x.0, |left, (op, right)| {
-					(
-						left.0.start..right.0.end,
-						BinOp(op, Box::new(left), Box::new(right)),
-					)
-				})
+		};
+
+		let postfix_parser = index_parser
+			.clone()
+			.then(increment.clone().map_with_span(|op, s| (op, s)).or_not())
+			.map(move |(exp, op)| match op {
+				Some((op, s)) => {
+					let span = exp.0.start..s.end;
+					step(op, exp, true, span)
+				}
+				None => exp,
+			});
+
+		#[derive(Clone)]
+		enum Unary {
+			Prefix(Prefix),
+			Increment(Op),
+		}
+
+		let prefix = just(Token::Operator("-".to_string()))
+			.to(Unary::Prefix(Prefix::Neg))
+			.or(just(Token::Operator("!".to_string())).to(Unary::Prefix(Prefix::Not)))
+			.or(just(Token::Operator("~".to_string())).to(Unary::Prefix(Prefix::BitNot)))
+			.or(increment.map(Unary::Increment));
+		// The lexer gives `2147483648` as `i32::MIN`, which is only valid right after a `-`
+		let min_int = |exp: &SpannedExpression| {
+			matches!(exp.1, Terminal(Symbol::Data(RawData::Int(i32::MIN))))
+		};
+		let unary_parser = prefix
+			.map_with_span(|op, s| (op, s))
+			.repeated()
+			.then(postfix_parser)
+			.validate(move |(ops, exp), _, emit| {
+				if min_int(&exp) && !matches!(ops.last(), Some((Unary::Prefix(Prefix::Neg), _))) {
+					emit(literal_error(exp.0.clone(), TOO_LARGE_INT));
+				}
+				(ops, exp)
+			})
+			.foldr(move |(op, span), exp| {
+				let span = span.start..exp.0.end;
+				match op {
+					Unary::Prefix(Prefix::Neg) if min_int(&exp) => (span, exp.1),
+					Unary::Prefix(op) => (span, OneOp(op, Box::new(exp))),
+					Unary::Increment(op) => step(op, exp, false, span),
+				}
 			})
 			.boxed();
 
-		let compare_parser = (add_sub_parser.clone())
-			.then(
-				(op_parser(Op::Le).or(op_parser(Op::Ge)))
-					.then(add_sub_parser.clone())
-					.repeated(),
-			)
-			.map(|x| {
-				x.1.into_iter().fold(x.0, |left, (op, right)| {
-					(
-						left.0.start..right.0.end,
-						Expression::BinOp(op, Box::new(left), Box::new(right)),
-					)
+		let mult_parser = bin_op_parser(
+			unary_parser,
+			op_parser(Op::Mult)
+				.or(op_parser(Op::Div))
+				.or(op_parser(Op::Mod)),
+		)
+		.boxed();
+		let add_sub_parser =
+			bin_op_parser(mult_parser, op_parser(Op::Add).or(op_parser(Op::Sub))).boxed();
+		let shift_parser =
+			bin_op_parser(add_sub_parser, op_parser(Op::Shl).or(op_parser(Op::Shr))).boxed();
+		let compare_parser = bin_op_parser(
+			shift_parser,
+			op_parser(Op::Lt)
+				.or(op_parser(Op::Gt))
+				.or(op_parser(Op::Le))
+				.or(op_parser(Op::Ge)),
+		)
+		.boxed();
+		let equal_parser =
+			bin_op_parser(compare_parser, op_parser(Op::Eq).or(op_parser(Op::Neq))).boxed();
+		let bit_and_parser = bin_op_parser(equal_parser, op_parser(Op::BitAnd)).boxed();
+		let bit_xor_parser = bin_op_parser(bit_and_parser, op_parser(Op::BitXor)).boxed();
+		let bit_or_parser = bin_op_parser(bit_xor_parser, op_parser(Op::BitOr)).boxed();
+		let and_parser = bin_op_parser(bit_or_parser, op_parser(Op::And)).boxed();
+		let or_parser = bin_op_parser(and_parser, op_parser(Op::Or)).boxed();
+
+		let if_parser = recursive(|if_parser| {
+			just(Token::If)
+				.ignore_then(exp.clone().map(Box::new))
+				.then(block_exp.clone().map(Box::new))
+				.then(
+					just(Token::Else)
+						.ignore_then(if_parser.or(block_exp.clone()))
+						.or_not(),
+				)
+				.map_with_span(|((cond, then), otherwise), span| {
+					// Without an else the result is null when the condition is false
+					let otherwise =
+						otherwise.unwrap_or((then.0.end..then.0.end, Block(Vec::new())));
+					(span, IfElse(cond, then, Box::new(otherwise)))
 				})
-			})
-			.boxed();
+		});
 
-		let equal_parser = (compare_parser.clone())
+		let match_parser = just(Token::Match)
+			.ignore_then(exp.clone().map(Box::new))
 			.then(
-				op_parser(Op::Eq)
-					.or(op_parser(Op::Neq))
-					.then(compare_parser.clone())
-					.repeated(),
+				pattern_parser()
+					.then(just(Token::If).ignore_then(exp.clone()).or_not())
+					.then_ignore(just(Token::Operator("=>".to_string())))
+					.then(exp.clone())
+					.map(|((pattern, guard), body)| MatchArm {
+						pattern,
+						guard,
+						body,
+					})
+					.then_ignore(separator().or_not())
+					.repeated()
+					.delimited_by(Token::StartBlock, Token::EndBlock),
 			)
-			.map(|x| {
-				x.1.into_iter().fold(x.0, |left, (op, right)| {
-					(
-						left.0.start..right.0.end,
-						Expression::BinOp(op, Box::new(left), Box::new(right)),
-					)
-				})
-			})
+			.map_with_span(|(value, arms), span| (span, Match(value, arms)))
+			.labelled("Match")
 			.boxed();
 
-		let if_parser = just(Token::If)
-			.ignore_then(exp.clone().map(Box::new))
-			.then(block_exp.clone().map(Box::new))
-			.then_ignore(just(Token::Else))
-			.then(block_exp.clone().map(Box::new))
-			.map_with_span(|x, span| (span, IfElse(x.0 .0, x.0 .1, x.1)));
 		let loop_parser = just(Token::While)
 			.ignore_then(exp.clone())
 			.then(block_exp.clone())
 			.map_with_span(|x, span| (span, Loop(Box::new(x.0), Box::new(x.1))));
 
+		// `exp` takes the `;` after it, which the header of a for loop needs to separate its parts
+		let mut unterminated: Recursive<Token, SpannedExpression, _> = Recursive::declare();
+		// Any part of the header can be left out, like in `for (;;)`
+		let for_header = unterminated
+			.clone()
+			.or_not()
+			.then_ignore(just(Token::Terminator))
+			.then(unterminated.clone().or_not())
+			.then_ignore(just(Token::Terminator))
+			.then(unterminated.clone().or_not())
+			.delimited_by(Token::StartBracket, Token::EndBracket)
+			.map_with_span(|((init, cond), step), span: Range<usize>| {
+				let empty = || (span.end..span.end, Block(Vec::new()));
+				let cond =
+					cond.unwrap_or((span.clone(), Terminal(Symbol::Data(RawData::Bool(true)))));
+				(init.unwrap_or_else(empty), cond, step.unwrap_or_else(empty))
+			});
+		let for_parser = just(Token::For)
+			.ignore_then(for_header)
+			.then(block_exp.clone())
+			.map_with_span(|((init, cond, step), body), span| {
+				let (init, cond, step) = (Box::new(init), Box::new(cond), Box::new(step));
+				(span, ForLoop(init, cond, step, Box::new(body)))
+			});
+
+		let for_of_parser = just(Token::For)
+			.ignore_then(
+				(just(Token::Let).to(false))
+					.or(just(Token::Constant).to(true))
+					.then(token_ident().map_with_span(|n, s| (n, s)))
+					.then_ignore(just(Token::Of))
+					.then(exp.clone())
+					.delimited_by(Token::StartBracket, Token::EndBracket),
+			)
+			.then(block_exp.clone())
+			.map_with_span(|(((constant, name), iterable), body), span| {
+				(
+					span,
+					ForOf(constant, name, Box::new(iterable), Box::new(body)),
+				)
+			});
+
+		let jump = just(Token::Break)
+			.to(Break)
+			.or(just(Token::Continue).to(Continue))
+			.or(just(Token::Return)
+				.ignore_then(exp.clone().map(Box::new).or_not())
+				.map(Return))
+			.map_with_span(|x, span| (span, x));
+
 		let type_declaration = just(Token::Type)
 			.ignore_then(token_ident())
 			.then(generics.clone())
 			.then_ignore(just(Token::Operator(String::from("="))))
 			.then(type_parser())
 			.labelled("Type assignment")
-			.map_with_span(|x, r| (r, TypeDeclaration(x.0 .0, x.0 .1, x.1)))
+			.map_with_span(|x, r| (r, TypeDeclaration(x.0 .0, x.0 .1, x.1, None)))
+			.boxed();
+
+		let payload = type_parser()
+			.separated_by(separator())
+			.delimited_by(Token::StartBracket, Token::EndBracket)
+			.map(|fields| {
+				fields
+					.into_iter()
+					.enumerate()
+					.map(|(i, ty)| (i.to_string(), ty))
+					.collect()
+			})
+			.or(token_ident()
+				.then_ignore(just(Token::Colon))
+				.then(type_parser())
+				.separated_by(just(Token::Terminator))
+				.allow_trailing()
+				.delimited_by(Token::StartBlock, Token::EndBlock))
+			.or_not()
+			.map(|fields| CustomType::Struct(fields.unwrap_or_default()));
+
+		let enum_declaration = just(Token::Enum)
+			.ignore_then(token_ident())
+			.then(
+				token_ident()
+					.map_with_span(|name, span| (name, span))
+					.then(payload)
+					.separated_by(separator())
+					.allow_trailing()
+					.delimited_by(Token::StartBlock, Token::EndBlock),
+			)
+			.map_with_span(|(name, variants), span| (span, EnumDeclaration(name, variants, None)))
+			.labelled("Enum")
+			.boxed();
+
+		let impl_parser = just(Token::Impl)
+			.ignore_then(token_ident().map_with_span(|n, s| (n, s)))
+			.then(generics.clone())
+			.then(
+				doc_comments()
+					.then(token_ident().map_with_span(|n, s| (n, s)))
+					.then(function)
+					.map(|((doc, name), func)| (name, func, doc))
+					.then_ignore(just(Token::Terminator).or_not())
+					.repeated()
+					.then_ignore(doc_comments())
+					.delimited_by(Token::StartBlock, Token::EndBlock),
+			)
+			.map_with_span(|((ty, generics), methods), span| (span, Impl(ty, generics, methods)))
+			.labelled("Impl")
 			.boxed();
 
 		let reassign = index_parser
@@ -358,9 +748,27 @@ fn exp_parser<'a>() -> impl Parser<Token, (Range<usize>, Expression), Error = Ch
 				.map_with_span(|x, s| (s, Terminal(x))))
 			.map(Box::new)
 			.then_ignore(just(Token::Operator("=".to_string())))
-			.then(exp.clone().map(Box::new))
+			.then(unterminated.clone().map(Box::new))
 			.map_with_span(|x, span| (span, Assign(x.0, x.1)));
 
+		let compound_op = just(Token::Operator("+=".to_string()))
+			.to(Op::Add)
+			.or(just(Token::Operator("-=".to_string())).to(Op::Sub))
+			.or(just(Token::Operator("*=".to_string())).to(Op::Mult))
+			.or(just(Token::Operator("/=".to_string())).to(Op::Div))
+			.or(just(Token::Operator("%=".to_string())).to(Op::Mod));
+
+		let compound_assign = index_parser
+			.clone()
+			.then(compound_op)
+			.then(unterminated.clone())
+			.map_with_span(|((target, op), value), span| {
+				(
+					span,
+					CompoundAssign(op, Box::new(target), Box::new(value), false),
+				)
+			});
+
 		let is_external = just(Token::Exported).or_not().map(|x| x.is_some());
 
 		let declaration = is_external
@@ -368,16 +776,15 @@ fn exp_parser<'a>() -> impl Parser<Token, (Range<usize>, Expression), Error = Ch
 			.then(token_ident().map_with_span(|n, s| (n, s)))
 			.then(just(Token::Colon).ignore_then(type_parser()).or_not())
 			.then_ignore(just(Token::Operator("=".to_string())))
-			.then(exp.clone().map(Box::new))
+			.then(unterminated.clone().map(Box::new))
 			.map_with_span(|x, span| {
 				(
 					span,
-					InitAssign(x.0 .0 .0 .0, x.0 .0 .0 .1, x.0 .0 .1, x.0 .1, x.1),
+					InitAssign(x.0 .0 .0 .0, x.0 .0 .0 .1, x.0 .0 .1, x.0 .1, x.1, None),
 				)
 			})
 			.labelled("Declaration");
-		let conversion = equal_parser
-			.clone()
+		let conversion = or_parser
 			.then(just(Token::As).ignore_then(type_parser()).repeated())
 			.map(|x| {
 				x.1.into_iter().fold(x.0, |left, right| {
@@ -387,24 +794,57 @@ fn exp_parser<'a>() -> impl Parser<Token, (Range<usize>, Expression), Error = Ch
 					)
 				})
 			});
+
+		let ternary = conversion
+			.then(
+				just(Token::Operator("?".to_string()))
+					.ignore_then(exp.clone())
+					.then_ignore(just(Token::Colon))
+					.then(unterminated.clone())
+					.or_not(),
+			)
+			.map_with_span(|(cond, branches), span| match branches {
+				Some((then, otherwise)) => (
+					span,
+					IfElse(Box::new(cond), Box::new(then), Box::new(otherwise)),
+				),
+				None => cond,
+			});
+
 		let expression = struct_exp
 			.or(if_parser)
+			.or(match_parser)
 			.or(loop_parser)
+			.or(for_of_parser)
+			.or(for_parser)
+			.or(jump)
 			.or(reassign)
+			.or(compound_assign)
 			.or(type_declaration.clone())
+			.or(impl_parser)
+			.or(enum_declaration)
 			.or(declaration)
-			.or(conversion)
+			.or(ternary)
 			.or(func_declaration.map_with_span(|x, s| (s, Terminal(x))))
 			.or(array_parser)
 			.boxed();
 
+		unterminated.define(expression.clone().or(block_exp.clone()));
+
 		expression
 			.then_ignore(just(Token::Terminator).or_not())
 			.or(block_exp)
 	})
 }
 
-pub fn parser() -> impl Parser<Token, (Vec<ImportFrom>, SpannedExpression), Error = Cheap<Token>> {
+/// A `}` at the top level has nothing to close, so it's reported and skipped over
+fn stray_end_block() -> impl Parser<Token, (), Error = Simple<Token>> + Clone {
+	just(Token::EndBlock)
+		.validate(|token, span, emit| emit(Simple::expected_input_found(span, [None], Some(token))))
+		.ignored()
+}
+
+pub fn parser() -> impl Parser<Token, (Vec<ImportFrom>, SpannedExpression), Error = Simple<Token>> {
 	let imports = just(Token::From)
 		.ignore_then(token_str())
 		.then_ignore(just(Token::Import))
@@ -421,11 +861,100 @@ pub fn parser() -> impl Parser<Token, (Vec<ImportFrom>, SpannedExpression), Erro
 		.then_ignore(just(Token::Terminator).or_not())
 		.map(|(file, imports)| ImportFrom { file, imports })
 		.repeated();
-	imports
-		.then(
-			exp_parser()
-				.repeated()
-				.map_with_span(|x, s| (s, Expression::Block(x))),
-		)
-		.then_ignore(end())
+	let body = statements(exp_parser())
+		.separated_by(stray_end_block())
+		.flatten()
+		.map_with_span(|x, s| (s, Expression::Block(x)));
+	imports.then(body).then_ignore(end())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::ast1::*;
+	use crate::errors::CompError;
+	use crate::runner::parse_text;
+
+	/// The top level statements of the program and any errors found while parsing it
+	fn parse(src: &str) -> (Vec<Expression>, Vec<CompError>) {
+		let (ast, errs) = parse_text(src);
+		match ast.map(|(_, (_, ast))| ast) {
+			Some(Expression::Block(statements)) => {
+				(statements.into_iter().map(|x| x.1).collect(), errs)
+			}
+			x => panic!("Expected a block but found {:?}", x),
+		}
+	}
+
+	fn declared_name(exp: &Expression) -> Option<&str> {
+		match exp {
+			Expression::InitAssign(_, _, (name, _), ..) => Some(name),
+			_ => None,
+		}
+	}
+
+	/// Every statement after the broken one is still there to be checked
+	fn assert_recovers(src: &str, code: i32) {
+		let (statements, errs) = parse(src);
+		assert_eq!(codes(&errs), [code], "{}", src);
+		assert_eq!(
+			statements.last().and_then(declared_name),
+			Some("b"),
+			"{}",
+			src
+		);
+	}
+
+	fn codes(errs: &[CompError]) -> Vec<i32> {
+		errs.iter().map(CompError::get_code).collect()
+	}
+
+	#[test]
+	fn recovers_from_broken_statements() {
+		assert_recovers("let a = 1 +;\nlet b = 2", 26);
+		let (statements, _) = parse("let a = 1 +;\nlet b = 2");
+		assert!(statements.contains(&Expression::Invalid));
+	}
+
+	#[test]
+	fn recovers_from_bad_characters() {
+		assert_recovers("let a = 1 #\nlet b = 2", 25);
+	}
+
+	#[test]
+	fn recovers_from_stray_braces() {
+		assert_recovers("let a = 1 }\nlet b = 2", 26);
+	}
+
+	#[test]
+	fn smallest_int() {
+		assert_eq!(codes(&parse("let a = -2147483648").1), []);
+		assert_eq!(codes(&parse("let a = 2147483648").1), [27]);
+		assert_eq!(codes(&parse("let a = match 1 { 2147483648 => 1 }").1), [27]);
+	}
+
+	#[test]
+	fn doc_comments_on_declarations() {
+		let (statements, errs) =
+			parse("/// The answer\nlet a = 42\nlet b = (/// Not here\nx: Int) => x");
+		assert_eq!(codes(&errs), []);
+		match &statements[0] {
+			Expression::InitAssign(.., doc) => assert_eq!(doc.as_deref(), Some("The answer")),
+			x => panic!("Expected a declaration but found {:?}", x),
+		}
+	}
+
+	#[test]
+	fn doc_comments_on_methods() {
+		let src = "/// Shared\nimpl P {\n/// Own\na(self: P) => 1\nb(self: P) => 2\n}";
+		let (statements, errs) = parse(src);
+		assert_eq!(codes(&errs), []);
+		match &statements[0] {
+			Expression::Impl(_, _, methods) => {
+				let docs = methods.iter().map(|x| x.2.as_deref()).collect::<Vec<_>>();
+				// Methods without their own docs get the ones on the impl
+				assert_eq!(docs, [Some("Own"), Some("Shared")]);
+			}
+			x => panic!("Expected an impl but found {:?}", x),
+		}
+	}
 }
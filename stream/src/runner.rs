@@ -1,12 +1,15 @@
 use crate::ast1::*;
 use crate::ast2::*;
 
-use crate::errors::CompError;
-use crate::lexer;
+use crate::errors::{CompError, Expected};
+use crate::lexer::{self, INVALID_LITERAL};
 use crate::map_vec;
 use crate::parser::*;
 use crate::settings::Settings;
+use chumsky::error::{Simple, SimpleReason};
 use chumsky::Parser;
+use std::hash::Hash;
+use std::ops::Range;
 use std::path::Path;
 
 use std::{collections::HashMap, fs};
@@ -55,6 +58,11 @@ impl ImportMap {
 			..self
 		}
 	}
+
+	/// Whether any of the file's errors stop it from being run, warnings don't
+	pub fn has_errors(&self) -> bool {
+		self.errors.iter().any(|e| !e.is_warning())
+	}
 }
 
 pub fn resolve_path(sub_path: &str) -> Option<String> {
@@ -84,54 +92,93 @@ pub fn parse_files(
 	} else {
 		return files;
 	};
-	let tokens = lexer::lexer()
-		.parse(src.clone())
-		.map_err(|x| panic!("{:?}", x))
-		.unwrap();
-	let len = tokens.len();
-	let parsed = parser().parse(chumsky::Stream::from_iter(len..len + 1, tokens.into_iter()));
-	match parsed {
-		Ok(ast) => {
-			let imports = normalise_deps(ast.0);
-			for import in imports.iter().flatten() {
-				let dep_name = import.file.clone();
-				if !files.contains_key(&dep_name) {
-					let sub_settings = Settings {
-						call_linker: false,
-						input_name: dep_name.clone(),
-						object_name: dep_name.replace(".srm", ".o"),
-						..settings
-					};
-					files = parse_files(sub_settings, files);
-				}
-			}
-			let lines = calc_lines(&src);
-			files.insert(
-				settings.input_name.clone(),
-				ImportMap {
-					file: settings.input_name.clone(),
-					settings,
-					line_numbers: lines,
-					text: Some(src),
-					depends_on: imports,
-					ast: Some(ast.1),
-					program: None,
-					compiled: false,
-					errors: Vec::new(),
-				},
-			);
-			files
-		}
-		Err(errs) => {
-			errs.into_iter().for_each(|e| println!("{:?}", e));
-			files
+	let (parsed, errors) = parse_text(&src);
+	let (imports, ast) = match parsed {
+		Some((imports, ast)) => (normalise_deps(imports), Some(ast)),
+		None => (Vec::new(), None),
+	};
+	for import in imports.iter().flatten() {
+		let dep_name = import.file.clone();
+		if !files.contains_key(&dep_name) {
+			let sub_settings = Settings {
+				call_linker: false,
+				input_name: dep_name.clone(),
+				object_name: dep_name.replace(".srm", ".o"),
+				..settings.clone()
+			};
+			files = parse_files(sub_settings, files);
 		}
 	}
+	let lines = calc_lines(&src);
+	files.insert(
+		settings.input_name.clone(),
+		ImportMap {
+			file: settings.input_name.clone(),
+			settings,
+			line_numbers: lines,
+			text: Some(src),
+			depends_on: imports,
+			ast,
+			program: None,
+			compiled: false,
+			errors,
+		},
+	);
+	files
+}
+
+/// Lexes and parses a source file, converting any failures into errors with their positions
+/// The parser recovers from most errors so there can be both an ast and errors
+pub fn parse_text(src: &str) -> (Option<(Vec<ImportFrom>, SpannedExpression)>, Vec<CompError>) {
+	let (tokens, errs) = lexer::lexer().parse_recovery(src);
+	let mut errs = map_vec!(errs, |e| {
+		convert_error(e, |c| format!("{:?}", c), CompError::UnexpectedCharacter)
+	});
+	let tokens = match tokens {
+		Some(tokens) => skip_stray_docs(tokens),
+		None => return (None, errs),
+	};
+	let len = src.len();
+	let (ast, parse_errs) =
+		parser().parse_recovery(chumsky::Stream::from_iter(len..len, tokens.into_iter()));
+	errs.extend(
+		parse_errs
+			.iter()
+			.map(|e| convert_error(e, |t| format!("'{}'", t), CompError::UnexpectedToken)),
+	);
+	(ast, errs)
+}
+
+fn convert_error<T: Hash + Eq>(
+	e: &Simple<T>,
+	show: fn(&T) -> String,
+	to_error: fn(String, Expected, Range<usize>) -> CompError,
+) -> CompError {
+	if let SimpleReason::Custom(msg) = e.reason() {
+		return if e.label() == Some(INVALID_LITERAL) {
+			CompError::InvalidLiteral(msg.clone(), e.span())
+		} else {
+			CompError::InvalidSyntax(msg.clone(), e.span())
+		};
+	}
+	let show = |x: Option<&T>| x.map_or_else(|| "end of input".to_string(), show);
+	let mut tokens = e.expected().map(|x| show(x.as_ref())).collect::<Vec<_>>();
+	tokens.sort();
+	tokens.dedup();
+	let expected = Expected {
+		tokens,
+		label: e.label().map(String::from),
+	};
+	to_error(show(e.found()), expected, e.span())
 }
 
 pub fn transform_files(name: &str, programs: &mut HashMap<String, ImportMap>) {
 	let mut global_scope = Scope::get_global_scope();
 	let mut import_errors = Vec::new();
+	// Files imported from several places only need to be checked once
+	if programs.get(name).map_or(true, |x| x.program.is_some()) {
+		return;
+	}
 	if let Some(program) = programs.get(name) {
 		for import in program.depends_on.clone() {
 			if let Ok(import) = import {
@@ -160,13 +207,13 @@ pub fn transform_files(name: &str, programs: &mut HashMap<String, ImportMap>) {
 	}
 
 	// Have to drop the borrow and get a mutable one
-	if let Some(program) = programs.get_mut(name) {
+	if let Some(program) = programs.get_mut(name) && let Some(ast) = &program.ast {
 		let prog = create_program(
-			program.ast.as_ref().unwrap(),
+			ast,
 			&mut global_scope,
 			&program.settings,
 		);
-		program.errors = import_errors;
+		program.errors.append(&mut import_errors);
 		program.program = Some(prog.collect_errors_into(&mut program.errors));
 	}
 }
@@ -193,6 +240,7 @@ impl Scope {
 			external: false,
 			typing: CompType::Callible(vec![CompType::Int], CompType::Ptr.boxed()),
 			declared_at: None,
+			doc: None,
 		});
 		global_scope
 	}
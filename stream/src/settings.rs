@@ -1,8 +1,71 @@
 #[derive(Clone, Debug, PartialEq)]
 pub struct Settings {
 	pub print_llvm: bool,
-	pub skip_optimizations: bool,
+	pub opt_level: OptLevel,
 	pub call_linker: bool,
 	pub input_name: String,
 	pub object_name: String,
+	/// The triple to compile for, defaults to the host when not set
+	pub target_triple: Option<String>,
+	/// Defaults to the host cpu when compiling for the host and "generic" otherwise
+	pub cpu: Option<String>,
+	pub cpu_features: Option<String>,
+	/// The files to write next to the object file, named after it with a different extension
+	pub emit: Vec<Emit>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum OptLevel {
+	/// No optimizations, the default for the language server as it never generates code
+	O0,
+	O1,
+	#[default]
+	O2,
+	O3,
+	/// Optimize for size over speed
+	Os,
+}
+
+impl OptLevel {
+	/// Parses the part of the flag after `-O`
+	pub fn from_flag(flag: &str) -> Option<Self> {
+		Some(match flag {
+			"0" => Self::O0,
+			"1" => Self::O1,
+			"2" => Self::O2,
+			"3" => Self::O3,
+			"s" => Self::Os,
+			_ => return None,
+		})
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Emit {
+	Object,
+	LlvmIr,
+	LlvmBitcode,
+	Assembly,
+}
+
+impl Emit {
+	/// Parses one of the comma separated kinds passed to `--emit`
+	pub fn from_flag(flag: &str) -> Option<Self> {
+		Some(match flag {
+			"obj" => Self::Object,
+			"llvm-ir" => Self::LlvmIr,
+			"llvm-bc" => Self::LlvmBitcode,
+			"asm" => Self::Assembly,
+			_ => return None,
+		})
+	}
+
+	pub fn extension(&self) -> &'static str {
+		match self {
+			Self::Object => "o",
+			Self::LlvmIr => "ll",
+			Self::LlvmBitcode => "bc",
+			Self::Assembly => "s",
+		}
+	}
 }